@@ -1,10 +1,23 @@
-use {serde::Serialize, std::collections::BTreeMap};
+use {
+    crate::{params::Parameters, skeleton::Skeleton},
+    glam::{EulerRot, Quat, Vec3},
+    serde::{Deserialize, Deserializer, Serialize, Serializer},
+    std::{
+        collections::{BTreeMap, HashMap},
+        error, fmt, str,
+    },
+};
 
 type Keyframes = BTreeMap<String, Vec<Keyframe>>;
 
+/// An action with keys grouped by bone names.
+///
+/// It's written as a map of keyframes, and additive
+/// actions wrap it with the `additive` flag.
 #[derive(Default)]
 pub struct Action {
     keyframes: Keyframes,
+    additive: bool,
 }
 
 impl Action {
@@ -25,20 +38,274 @@ impl Action {
         }
     }
 
-    pub(crate) fn is_empty(&self) -> bool {
-        self.keyframes.is_empty()
+    pub(crate) fn insert_keys(&mut self, bone: String, keys: Vec<Keyframe>) {
+        self.keyframes.insert(bone, keys);
+    }
+
+    /// Converts Euler rotations of all bones to quaternions.
+    ///
+    /// The rotation order of each bone is taken from `orders`, XYZ by default.
+    pub(crate) fn make_quaternions(&mut self, orders: &HashMap<String, Order>) {
+        for (bone, keys) in &mut self.keyframes {
+            let order = orders.get(bone).copied().unwrap_or_default();
+            let mut near = Value::default();
+            for key in keys {
+                key.val.euler_to_quat(order, &near);
+                near = key.val;
+            }
+        }
+    }
+
+    /// Rounds all values of the action.
+    pub(crate) fn round(&mut self) {
+        let params = Parameters::get();
+        for key in self.keyframes.values_mut().flatten() {
+            let input = key.input;
+            for comp in key.val.components_mut() {
+                [_, comp.output] = (params.act_fn)([input, comp.output]);
+                if let Interpolation::Bezier(handles) | Interpolation::Hermite(handles) =
+                    &mut comp.int
+                {
+                    *handles = (params.bez_fn)(*handles);
+                }
+            }
+        }
+    }
+
+    /// Evaluates the pose of the bone at the given time.
+    ///
+    /// The pose is relative to the bone's rest pose. The rotation is
+    /// returned as it was animated, in Euler angles or as a quaternion.
+    /// Channels that aren't animated take their identity values.
+    pub fn sample(&self, bone: &str, time: f32) -> Option<Pose> {
+        let keys = self.keyframes.get(bone)?;
+        let eval = |get: fn(&Value) -> Option<Component>, default| {
+            let curve: Vec<_> = keys
+                .iter()
+                .filter_map(|key| Some((key.input, get(&key.val)?)))
+                .collect();
+
+            eval_curve(&curve, time).unwrap_or(default)
+        };
+
+        let pos = [
+            eval(|val| val.lx, 0.),
+            eval(|val| val.ly, 0.),
+            eval(|val| val.lz, 0.),
+        ];
+
+        let rot = if keys.iter().any(|key| key.val.quat().is_some()) {
+            let q = Quat::from_xyzw(
+                eval(|val| val.qx, 0.),
+                eval(|val| val.qy, 0.),
+                eval(|val| val.qz, 0.),
+                eval(|val| val.qw, 1.),
+            );
+
+            Rotation::Quat(q.normalize().to_array())
+        } else {
+            Rotation::Euler([
+                eval(|val| val.rx, 0.),
+                eval(|val| val.ry, 0.),
+                eval(|val| val.rz, 0.),
+            ])
+        };
+
+        let scale = [
+            eval(|val| val.sx, 1.),
+            eval(|val| val.sy, 1.),
+            eval(|val| val.sz, 1.),
+        ];
+
+        Some(Pose { pos, rot, scale })
+    }
+
+    /// Evaluates poses of all bones at the given time.
+    pub fn pose_at(&self, time: f32) -> BTreeMap<String, Pose> {
+        self.keyframes
+            .keys()
+            .filter_map(|bone| Some((bone.clone(), self.sample(bone, time)?)))
+            .collect()
+    }
+
+    /// Evaluates the local transform of the bone at the given time.
+    ///
+    /// Euler rotations are converted to a quaternion with the given order.
+    pub(crate) fn transform_at(&self, bone: &str, input: f32, order: Order) -> Option<Transform> {
+        let Pose { pos, rot, scale } = self.sample(bone, input)?;
+        Some(Transform {
+            pos,
+            rot: rot.to_quat(order),
+            scale,
+        })
+    }
+
+    /// Rebuilds the action by transforming the curve of each channel.
+    pub(crate) fn map_curves<F>(&self, f: F) -> Self
+    where
+        F: Fn(Vec<(f32, Component)>) -> Vec<(f32, Component)>,
+    {
+        let mut out = Self {
+            keyframes: Keyframes::new(),
+            additive: self.additive,
+        };
+
+        for (bone, keys) in &self.keyframes {
+            for (_, get, make, _) in CHANNELS {
+                let curve = keys
+                    .iter()
+                    .filter_map(|key| Some((key.input, get(&key.val)?)))
+                    .collect();
+
+                for (input, comp) in f(curve) {
+                    out.insert_channel(bone.clone(), input, make(comp));
+                }
+            }
+        }
+
+        out.fill_eulers();
+        out
+    }
+
+    /// Fills Euler angles of each key where the rotation is partly keyed.
+    pub(crate) fn fill_eulers(&mut self) {
+        for keys in self.keyframes.values_mut() {
+            fill_eulers(keys);
+        }
+    }
+
+    /// Returns the time range of all keys.
+    pub(crate) fn range(&self) -> Option<(f32, f32)> {
+        let keys = self.keyframes.values().flatten();
+        let start = keys.clone().map(|key| key.input).min_by(f32::total_cmp)?;
+        let end = keys.map(|key| key.input).max_by(f32::total_cmp)?;
+        Some((start, end))
     }
 
     pub fn keyframes(&self) -> &Keyframes {
         &self.keyframes
     }
+
+    /// Returns the action with bones replaced by their indices in the skeleton.
+    ///
+    /// Bones missing from the skeleton are skipped, parsing with
+    /// [`skeleton`](crate::Options::skeleton) option reports them as warnings.
+    pub fn indexed(&self, sk: &Skeleton) -> Indexed<'_> {
+        Indexed {
+            keyframes: self
+                .keyframes
+                .iter()
+                .filter_map(|(bone, keys)| Some((sk.resolve(bone)?, &keys[..])))
+                .collect(),
+            additive: self.additive,
+        }
+    }
+
+    pub(crate) fn keyframes_mut(&mut self) -> &mut Keyframes {
+        &mut self.keyframes
+    }
+
+    /// Checks if the action is applied on top of other poses.
+    pub fn is_additive(&self) -> bool {
+        self.additive
+    }
+
+    pub(crate) fn make_additive(&mut self) {
+        self.additive = true;
+    }
 }
 
-#[derive(Clone, Copy, Serialize)]
-#[serde(into = "(f32, Value)")]
+impl Serialize for Action {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_keyframes(&self.keyframes, self.additive, s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Action {
+    /// Reads an action with bone names, as it's written by [`Serialize`].
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Additive {
+                additive: bool,
+                keyframes: Keyframes,
+            },
+            Plain(Keyframes),
+        }
+
+        Ok(match Repr::deserialize(d)? {
+            Repr::Additive {
+                additive,
+                keyframes,
+            } => Self {
+                keyframes,
+                additive,
+            },
+            Repr::Plain(keyframes) => Self {
+                keyframes,
+                additive: false,
+            },
+        })
+    }
+}
+
+/// An action with bones identified by skeleton indices.
+pub struct Indexed<'a> {
+    keyframes: BTreeMap<u16, &'a [Keyframe]>,
+    additive: bool,
+}
+
+impl Indexed<'_> {
+    pub fn keyframes(&self) -> &BTreeMap<u16, &[Keyframe]> {
+        &self.keyframes
+    }
+}
+
+impl Serialize for Indexed<'_> {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_keyframes(&self.keyframes, self.additive, s)
+    }
+}
+
+/// Writes keyframes of additive actions with a flag,
+/// and keyframes of other ones as is.
+fn serialize_keyframes<K, S>(keyframes: K, additive: bool, s: S) -> Result<S::Ok, S::Error>
+where
+    K: Serialize,
+    S: Serializer,
+{
+    #[derive(Serialize)]
+    struct Additive<K> {
+        additive: bool,
+        keyframes: K,
+    }
+
+    if additive {
+        Additive {
+            additive,
+            keyframes,
+        }
+        .serialize(s)
+    } else {
+        keyframes.serialize(s)
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(from = "(f32, Value)", into = "(f32, Value)")]
 pub struct Keyframe {
-    input: f32,
-    val: Value,
+    pub(crate) input: f32,
+    pub(crate) val: Value,
 }
 
 impl From<Keyframe> for (f32, Value) {
@@ -47,50 +314,719 @@ impl From<Keyframe> for (f32, Value) {
     }
 }
 
-#[derive(Clone, Copy, Default, Serialize)]
-struct Value {
-    pub rx: Rotation,
-    pub ry: Rotation,
-    pub rz: Rotation,
+impl From<(f32, Value)> for Keyframe {
+    fn from((input, val): (f32, Value)) -> Self {
+        Self { input, val }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub(crate) struct Value {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ry: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rz: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qw: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qx: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qy: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qz: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lx: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ly: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lz: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sx: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sy: Option<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sz: Option<Component>,
 }
 
 impl Value {
+    /// Returns Euler angles if all its components are keyed.
+    pub(crate) fn euler(&self) -> Option<[f32; 3]> {
+        Some([self.rx?.output, self.ry?.output, self.rz?.output])
+    }
+
+    /// Returns the quaternion in `[x, y, z, w]` form if all its components are keyed.
+    pub(crate) fn quat(&self) -> Option<[f32; 4]> {
+        Some([
+            self.qx?.output,
+            self.qy?.output,
+            self.qz?.output,
+            self.qw?.output,
+        ])
+    }
+
+    /// Returns all keyed components of the value.
+    pub(crate) fn components_mut(&mut self) -> impl Iterator<Item = &mut Component> {
+        [
+            &mut self.rx,
+            &mut self.ry,
+            &mut self.rz,
+            &mut self.qw,
+            &mut self.qx,
+            &mut self.qy,
+            &mut self.qz,
+            &mut self.lx,
+            &mut self.ly,
+            &mut self.lz,
+            &mut self.sx,
+            &mut self.sy,
+            &mut self.sz,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Maps the rotation of the value with its bezier handles.
+    ///
+    /// The Euler angles use the given rotation order. New angles
+    /// are chosen to be closest to the `near` ones to avoid jumps.
+    pub(crate) fn map_rotation<F>(mut self, order: Order, near: &Self, f: F) -> Self
+    where
+        F: Fn(Quat) -> Quat,
+    {
+        if let (Some(rx), Some(ry), Some(rz)) = (self.rx, self.ry, self.rz) {
+            let near = near.euler().unwrap_or_default();
+            let [rx, ry, rz] = map_components([rx, ry, rz], near, |euler, near| {
+                order.to_euler(f(order.to_quat(euler)), near)
+            });
+
+            (self.rx, self.ry, self.rz) = (Some(rx), Some(ry), Some(rz));
+        }
+
+        if let (Some(qx), Some(qy), Some(qz), Some(qw)) = (self.qx, self.qy, self.qz, self.qw) {
+            let near = near.quat().unwrap_or(Quat::IDENTITY.to_array());
+            let [qx, qy, qz, qw] = map_components([qx, qy, qz, qw], near, |quat, near| {
+                closest(f(Quat::from_array(quat)), near)
+            });
+
+            (self.qx, self.qy, self.qz, self.qw) = (Some(qx), Some(qy), Some(qz), Some(qw));
+        }
+
+        self
+    }
+
+    /// Maps the location of the value with its bezier handles
+    /// if all location components are keyed.
+    pub(crate) fn map_location<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Vec3) -> Vec3,
+    {
+        if let (Some(lx), Some(ly), Some(lz)) = (self.lx, self.ly, self.lz) {
+            let [lx, ly, lz] = map_components([lx, ly, lz], [0.; 3], |loc, _| {
+                f(Vec3::from_array(loc)).to_array()
+            });
+
+            (self.lx, self.ly, self.lz) = (Some(lx), Some(ly), Some(lz));
+        }
+
+        self
+    }
+
+    /// Replaces Euler angles of the value with a quaternion.
+    pub(crate) fn euler_to_quat(&mut self, order: Order, near: &Self) {
+        if let (Some(rx), Some(ry), Some(rz)) = (self.rx, self.ry, self.rz) {
+            let near = near.quat().unwrap_or(Quat::IDENTITY.to_array());
+            let [qx, qy, qz, qw] = map_components([rx, ry, rz], near, |euler, near| {
+                closest(order.to_quat(euler), near)
+            });
+
+            (self.rx, self.ry, self.rz) = (None, None, None);
+            (self.qx, self.qy, self.qz, self.qw) = (Some(qx), Some(qy), Some(qz), Some(qw));
+        }
+    }
+
     fn with(mut self, chan: Channel) -> Self {
         match chan {
-            Channel::RotationX(rx) => self.rx = rx,
-            Channel::RotationY(ry) => self.ry = ry,
-            Channel::RotationZ(rz) => self.rz = rz,
+            Channel::RotationX(rx) => self.rx = Some(rx),
+            Channel::RotationY(ry) => self.ry = Some(ry),
+            Channel::RotationZ(rz) => self.rz = Some(rz),
+            Channel::QuaternionW(qw) => self.qw = Some(qw),
+            Channel::QuaternionX(qx) => self.qx = Some(qx),
+            Channel::QuaternionY(qy) => self.qy = Some(qy),
+            Channel::QuaternionZ(qz) => self.qz = Some(qz),
+            Channel::LocationX(lx) => self.lx = Some(lx),
+            Channel::LocationY(ly) => self.ly = Some(ly),
+            Channel::LocationZ(lz) => self.lz = Some(lz),
+            Channel::ScaleX(sx) => self.sx = Some(sx),
+            Channel::ScaleY(sy) => self.sy = Some(sy),
+            Channel::ScaleZ(sz) => self.sz = Some(sz),
         }
 
         self
     }
 }
 
-#[derive(Clone, Copy, Default, Serialize)]
-#[serde(into = "(f32, Interpolation)")]
-pub(crate) struct Rotation {
+pub(crate) type Get = fn(&Value) -> Option<Component>;
+pub(crate) type Make = fn(Component) -> Channel;
+
+/// Kind of a value component.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Angle,
+    Quaternion,
+    Position,
+}
+
+/// Supported channels by collada name with getters of value components and their kinds.
+pub(crate) const CHANNELS: [(&str, Get, Make, Kind); 13] = [
+    (
+        "rotation_euler_X",
+        |val| val.rx,
+        Channel::RotationX,
+        Kind::Angle,
+    ),
+    (
+        "rotation_euler_Y",
+        |val| val.ry,
+        Channel::RotationY,
+        Kind::Angle,
+    ),
+    (
+        "rotation_euler_Z",
+        |val| val.rz,
+        Channel::RotationZ,
+        Kind::Angle,
+    ),
+    (
+        "rotation_quaternion_W",
+        |val| val.qw,
+        Channel::QuaternionW,
+        Kind::Quaternion,
+    ),
+    (
+        "rotation_quaternion_X",
+        |val| val.qx,
+        Channel::QuaternionX,
+        Kind::Quaternion,
+    ),
+    (
+        "rotation_quaternion_Y",
+        |val| val.qy,
+        Channel::QuaternionY,
+        Kind::Quaternion,
+    ),
+    (
+        "rotation_quaternion_Z",
+        |val| val.qz,
+        Channel::QuaternionZ,
+        Kind::Quaternion,
+    ),
+    (
+        "location_X",
+        |val| val.lx,
+        Channel::LocationX,
+        Kind::Position,
+    ),
+    (
+        "location_Y",
+        |val| val.ly,
+        Channel::LocationY,
+        Kind::Position,
+    ),
+    (
+        "location_Z",
+        |val| val.lz,
+        Channel::LocationZ,
+        Kind::Position,
+    ),
+    ("scale_X", |val| val.sx, Channel::ScaleX, Kind::Position),
+    ("scale_Y", |val| val.sy, Channel::ScaleY, Kind::Position),
+    ("scale_Z", |val| val.sz, Channel::ScaleZ, Kind::Position),
+];
+
+/// Local pose of a bone sampled from an action.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Pose {
+    pub pos: [f32; 3],
+    pub rot: Rotation,
+    pub scale: [f32; 3],
+}
+
+/// Rotation of a bone pose.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    /// Euler angles in radians.
+    Euler([f32; 3]),
+
+    /// A quaternion in xyzw order.
+    Quat([f32; 4]),
+}
+
+impl Rotation {
+    /// Converts the rotation to a quaternion in xyzw order.
+    ///
+    /// Euler angles are applied in the given order.
+    pub fn to_quat(self, order: Order) -> [f32; 4] {
+        match self {
+            Self::Euler(euler) => order.to_quat(euler).to_array(),
+            Self::Quat(q) => q,
+        }
+    }
+}
+
+/// Full local transform of a bone.
+#[derive(Clone, Copy, Serialize)]
+pub struct Transform {
+    pub pos: [f32; 3],
+    pub rot: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+type Field = fn(&mut Value) -> &mut Option<Component>;
+
+/// Fills location components missing from partly keyed locations,
+/// so the whole location can be transformed.
+pub(crate) fn fill_locations(keys: &mut [Keyframe]) {
+    fill(
+        keys,
+        [|val| &mut val.lx, |val| &mut val.ly, |val| &mut val.lz],
+    );
+}
+
+/// Fills Euler angles missing from partly keyed rotations,
+/// so the axes keyed at different times are written together.
+pub(crate) fn fill_eulers(keys: &mut [Keyframe]) {
+    fill(
+        keys,
+        [|val| &mut val.rx, |val| &mut val.ry, |val| &mut val.rz],
+    );
+}
+
+/// Fills components of the group missing from keys where the group is partly keyed.
+///
+/// A missing component takes the value of its curve at the key,
+/// or the rest value if the component isn't animated at all.
+fn fill(keys: &mut [Keyframe], group: [Field; 3]) {
+    let curves = group.map(|field| {
+        let curve: Vec<_> = keys
+            .iter()
+            .filter_map(|key| {
+                let mut val = key.val;
+                Some((key.input, (*field(&mut val))?))
+            })
+            .collect();
+
+        curve
+    });
+
+    for key in keys {
+        let val = &mut key.val;
+        if group.iter().all(|field| field(val).is_none()) {
+            continue;
+        }
+
+        for (field, curve) in group.iter().zip(&curves) {
+            let comp = field(val);
+            if comp.is_none() {
+                *comp = Some(Component {
+                    output: eval_curve(curve, key.input).unwrap_or(0.),
+                    int: Interpolation::Linear,
+                });
+            }
+        }
+    }
+}
+
+/// Evaluates the curve of sorted keys at the given time.
+///
+/// A segment is interpolated by its left key. Values before the first key
+/// and after the last one are held constant, as Blender does.
+pub(crate) fn eval_curve(curve: &[(f32, Component)], input: f32) -> Option<f32> {
+    let (&(first, comp), &(last, last_comp)) = (curve.first()?, curve.last()?);
+    if input <= first {
+        return Some(comp.output);
+    }
+
+    if input >= last {
+        return Some(last_comp.output);
+    }
+
+    let idx = curve.partition_point(|&(x, _)| x <= input);
+    let (x0, k0) = curve[idx - 1];
+    let (x1, k1) = curve[idx];
+    let (y0, y1) = (k0.output, k1.output);
+    let val = match k0.int {
+        Interpolation::Step => y0,
+        Interpolation::Linear => {
+            let t = (input - x0) / (x1 - x0);
+            y0 + (y1 - y0) * t
+        }
+        Interpolation::Bezier(_) | Interpolation::Hermite(_) => {
+            let [_, _, rx, ry] = k0.int.handles().unwrap_or_default();
+            let [lx, ly, _, _] = k1.int.handles().unwrap_or_default();
+
+            // Keep handles inside the segment so the curve is a function of time
+            let (cx0, cx1) = ((x0 + rx).clamp(x0, x1), (x1 + lx).clamp(x0, x1));
+            let t = solve_bezier([x0, cx0, cx1, x1], input);
+            bezier([y0, y0 + ry, y1 + ly, y1], t)
+        }
+    };
+
+    Some(val)
+}
+
+fn bezier([p0, p1, p2, p3]: [f32; 4], t: f32) -> f32 {
+    let s = 1. - t;
+    s * s * s * p0 + 3. * s * s * t * p1 + 3. * s * t * t * p2 + t * t * t * p3
+}
+
+/// Finds a parameter of the monotonic bezier curve where it takes the value.
+pub(crate) fn solve_bezier(ps: [f32; 4], val: f32) -> f32 {
+    let (mut lo, mut hi) = (0., 1.);
+    for _ in 0..32 {
+        let mid = (lo + hi) / 2.;
+        if bezier(ps, mid) < val {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    (lo + hi) / 2.
+}
+
+/// Maps values of components together with their bezier handles.
+///
+/// The `f` function takes a point and a point close to the expected result.
+/// If any component has bezier or hermite interpolation, all new components
+/// get bezier one. New components are stepped only if all components are.
+fn map_components<const N: usize, const M: usize, F>(
+    comps: [Component; N],
+    near: [f32; M],
+    f: F,
+) -> [Component; M]
+where
+    F: Fn([f32; N], [f32; M]) -> [f32; M],
+{
+    use std::array;
+
+    let point = |side: usize| {
+        comps.map(|comp| match comp.int.handles() {
+            Some(handles) => comp.output + handles[side * 2 + 1],
+            None => comp.output,
+        })
+    };
+
+    let new = f(comps.map(|comp| comp.output), near);
+    let handles = comps.iter().find_map(|comp| comp.int.handles());
+    let ints = match handles {
+        Some([lx, _, rx, _]) => {
+            let left = f(point(0), new);
+            let right = f(point(1), new);
+            array::from_fn(|i| Interpolation::Bezier([lx, left[i] - new[i], rx, right[i] - new[i]]))
+        }
+        None if comps
+            .iter()
+            .all(|comp| matches!(comp.int, Interpolation::Step)) =>
+        {
+            [Interpolation::Step; M]
+        }
+        None => [Interpolation::Linear; M],
+    };
+
+    array::from_fn(|i| Component {
+        output: new[i],
+        int: ints[i],
+    })
+}
+
+/// Returns the quaternion or its negation, whichever is closer to the `near` one.
+fn closest(q: Quat, near: [f32; 4]) -> [f32; 4] {
+    if q.dot(Quat::from_array(near)) < 0. {
+        (-q).to_array()
+    } else {
+        q.to_array()
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(from = "(f32, Interpolation)", into = "(f32, Interpolation)")]
+pub(crate) struct Component {
     pub output: f32,
     pub int: Interpolation,
 }
 
-impl From<Rotation> for (f32, Interpolation) {
-    fn from(Rotation { output, int }: Rotation) -> Self {
+impl From<Component> for (f32, Interpolation) {
+    fn from(Component { output, int }: Component) -> Self {
         (output, int)
     }
 }
 
-#[derive(Clone, Copy, Default, Serialize)]
+impl From<(f32, Interpolation)> for Component {
+    fn from((output, int): (f32, Interpolation)) -> Self {
+        Self { output, int }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
 pub(crate) enum Interpolation {
     #[default]
     #[serde(rename = "l")]
     Linear,
     #[serde(rename = "b")]
     Bezier([f32; 4]),
+    #[serde(rename = "s")]
+    Step,
+    #[serde(rename = "h")]
+    Hermite([f32; 4]),
+}
+
+impl Interpolation {
+    /// Returns bezier handle offsets of the interpolation.
+    pub fn handles(self) -> Option<[f32; 4]> {
+        match self {
+            Self::Linear | Self::Step => None,
+            Self::Bezier(handles) => Some(handles),
+            Self::Hermite(tangents) => {
+                let [ix, iy, ox, oy] = tangents.map(|t| t / 3.);
+                Some([-ix, -iy, ox, oy])
+            }
+        }
+    }
+}
+
+/// A coordinate axis.
+#[derive(Clone, Copy)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub(crate) fn vec(self) -> Vec3 {
+        match self {
+            Self::X => Vec3::X,
+            Self::Y => Vec3::Y,
+            Self::Z => Vec3::Z,
+        }
+    }
+}
+
+impl str::FromStr for Axis {
+    type Err = UnknownAxis;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x" => Ok(Self::X),
+            "y" => Ok(Self::Y),
+            "z" => Ok(Self::Z),
+            _ => Err(UnknownAxis),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownAxis;
+
+impl fmt::Display for UnknownAxis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown axis")
+    }
+}
+
+impl error::Error for UnknownAxis {}
+
+/// Euler rotation order as named in Blender.
+///
+/// For example, `XYZ` rotates around the X axis first, then Y and Z.
+#[derive(Clone, Copy, Default, Deserialize)]
+pub enum Order {
+    #[default]
+    #[serde(rename = "XYZ")]
+    Xyz,
+    #[serde(rename = "XZY")]
+    Xzy,
+    #[serde(rename = "YXZ")]
+    Yxz,
+    #[serde(rename = "YZX")]
+    Yzx,
+    #[serde(rename = "ZXY")]
+    Zxy,
+    #[serde(rename = "ZYX")]
+    Zyx,
+}
+
+impl Order {
+    /// Returns the glam rotation sequence and indices of angles in it.
+    ///
+    /// Blender's orders are extrinsic, so they are reversed for glam.
+    fn sequence(self) -> (EulerRot, [usize; 3]) {
+        match self {
+            Self::Xyz => (EulerRot::ZYX, [2, 1, 0]),
+            Self::Xzy => (EulerRot::YZX, [1, 2, 0]),
+            Self::Yxz => (EulerRot::ZXY, [2, 0, 1]),
+            Self::Yzx => (EulerRot::XZY, [0, 2, 1]),
+            Self::Zxy => (EulerRot::YXZ, [1, 0, 2]),
+            Self::Zyx => (EulerRot::XYZ, [0, 1, 2]),
+        }
+    }
+
+    pub(crate) fn to_quat(self, euler: [f32; 3]) -> Quat {
+        let (rot, [a, b, c]) = self.sequence();
+        Quat::from_euler(rot, euler[a], euler[b], euler[c])
+    }
+
+    /// Converts the quaternion to Euler angles closest to the `near` ones.
+    pub(crate) fn to_euler(self, q: Quat, near: [f32; 3]) -> [f32; 3] {
+        use std::f32::consts::{PI, TAU};
+
+        let (rot, [a, b, c]) = self.sequence();
+        let (u, v, w) = q.to_euler(rot);
+        let mut euler = [0.; 3];
+        (euler[a], euler[b], euler[c]) = (u, v, w);
+        for (a, n) in euler.iter_mut().zip(near) {
+            *a += ((n - *a + PI) / TAU).floor() * TAU;
+        }
+
+        euler
+    }
 }
 
 #[derive(Clone, Copy)]
 pub(crate) enum Channel {
-    RotationX(Rotation),
-    RotationY(Rotation),
-    RotationZ(Rotation),
+    RotationX(Component),
+    RotationY(Component),
+    RotationZ(Component),
+    QuaternionW(Component),
+    QuaternionX(Component),
+    QuaternionY(Component),
+    QuaternionZ(Component),
+    LocationX(Component),
+    LocationY(Component),
+    LocationZ(Component),
+    ScaleX(Component),
+    ScaleY(Component),
+    ScaleZ(Component),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eval() {
+        let key = |output, int| Component { output, int };
+        let curve = [
+            (0., key(0., Interpolation::Linear)),
+            (1., key(2., Interpolation::Step)),
+            (2., key(4., Interpolation::Bezier([0., 0., 0.5, 0.]))),
+            (3., key(0., Interpolation::Bezier([-0.5, 0., 0., 0.]))),
+        ];
+
+        let at = |input| eval_curve(&curve, input).expect("value");
+        assert_eq!(at(-1.), 0., "hold before the first key");
+        assert_eq!(at(0.5), 1., "linear");
+        assert_eq!(at(1.5), 2., "step");
+        assert!((at(2.5) - 2.).abs() < 1e-4, "symmetric bezier");
+        assert!(at(2.25) > 3., "bezier eases out");
+        assert_eq!(at(4.), 0., "hold after the last key");
+    }
+
+    #[test]
+    fn sample() {
+        let mut action = Action::default();
+        let key = |output| Component {
+            output,
+            int: Interpolation::Linear,
+        };
+
+        let chans = [
+            (0., Channel::RotationX(key(0.))),
+            (1., Channel::RotationX(key(1.))),
+            (1., Channel::LocationY(key(2.))),
+        ];
+
+        for (input, chan) in chans {
+            action.insert_channel("bone".to_owned(), input, chan);
+        }
+
+        let pose = action.sample("bone", 0.5).expect("pose");
+        assert_eq!(
+            pose.rot,
+            Rotation::Euler([0.5, 0., 0.]),
+            "interpolated rotation"
+        );
+        assert_eq!(pose.pos, [0., 2., 0.], "held location");
+        assert_eq!(pose.scale, [1.; 3], "identity scale");
+        assert!(action.sample("other", 0.).is_none(), "unknown bone");
+        assert_eq!(action.pose_at(2.).len(), 1, "all bones");
+    }
+
+    #[test]
+    fn partial_locations() {
+        let key = |output| Component {
+            output,
+            int: Interpolation::Linear,
+        };
+
+        let mut action = Action::default();
+        let chans = [
+            (0., Channel::LocationX(key(0.))),
+            (1., Channel::LocationY(key(2.))),
+            (2., Channel::LocationX(key(4.))),
+        ];
+
+        for (input, chan) in chans {
+            action.insert_channel("bone".to_owned(), input, chan);
+        }
+
+        let mut keys = action.keyframes()["bone"].clone();
+        fill_locations(&mut keys);
+        let locs: Vec<_> = keys
+            .iter()
+            .map(|key| [key.val.lx, key.val.ly, key.val.lz].map(|c| c.map(|c| c.output)))
+            .collect();
+
+        assert_eq!(
+            locs,
+            [
+                [Some(0.), Some(2.), Some(0.)],
+                [Some(2.), Some(2.), Some(0.)],
+                [Some(4.), Some(2.), Some(0.)],
+            ],
+            "components are taken from curves or the rest"
+        );
+    }
+
+    #[test]
+    fn euler_order() {
+        let [x, y, z] = [0.1, 0.2, 0.3];
+        let (rx, ry, rz) = (
+            Quat::from_rotation_x(x),
+            Quat::from_rotation_y(y),
+            Quat::from_rotation_z(z),
+        );
+
+        let cases = [
+            (Order::Xyz, rz * ry * rx),
+            (Order::Xzy, ry * rz * rx),
+            (Order::Yxz, rz * rx * ry),
+            (Order::Yzx, rx * rz * ry),
+            (Order::Zxy, ry * rx * rz),
+            (Order::Zyx, rx * ry * rz),
+        ];
+
+        for (order, expected) in cases {
+            let q = order.to_quat([x, y, z]);
+            assert!(q.abs_diff_eq(expected, 1e-6), "quaternion of the order");
+
+            let euler = order.to_euler(q, [0.; 3]);
+            for (a, b) in euler.into_iter().zip([x, y, z]) {
+                assert!((a - b).abs() < 1e-5, "euler round trip");
+            }
+        }
+    }
 }
@@ -0,0 +1,88 @@
+use {
+    crate::action::{Action, Component, Interpolation, Order, Pose, Value},
+    glam::Quat,
+    std::{
+        collections::{BTreeMap, HashMap},
+        str,
+    },
+};
+
+/// The reference pose of an additive action.
+#[derive(Clone)]
+pub enum Reference {
+    /// The rest pose of the skeleton.
+    Rest,
+
+    /// The first frame of the action with the name.
+    Action(String),
+}
+
+impl str::FromStr for Reference {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rest" => Ok(Self::Rest),
+            name => Ok(Self::Action(name.to_owned())),
+        }
+    }
+}
+
+/// Makes the action additive by subtracting the reference poses of bones.
+///
+/// Since action values are relative to the rest pose, there is nothing
+/// to subtract for the rest reference. Bones without a reference pose
+/// are kept as is.
+pub(crate) fn subtract(
+    action: &mut Action,
+    poses: &BTreeMap<String, Pose>,
+    orders: &HashMap<String, Order>,
+) {
+    action.make_additive();
+    for (bone, keys) in action.keyframes_mut() {
+        let Some(pose) = poses.get(bone) else {
+            continue;
+        };
+
+        let order = orders.get(bone).copied().unwrap_or_default();
+        let rot = Quat::from_array(pose.rot.to_quat(order)).inverse();
+        let mut near = Value::default();
+        for key in keys {
+            let mut val = key.val.map_rotation(order, &near, |q| rot * q);
+            let locs = [&mut val.lx, &mut val.ly, &mut val.lz];
+            for (comp, loc) in locs.into_iter().zip(pose.pos) {
+                *comp = comp.map(|comp| Component {
+                    output: comp.output - loc,
+                    int: comp.int,
+                });
+            }
+
+            let scales = [&mut val.sx, &mut val.sy, &mut val.sz];
+            for (comp, scale) in scales.into_iter().zip(pose.scale) {
+                if scale != 0. {
+                    *comp = comp.map(|comp| divide(comp, scale));
+                }
+            }
+
+            near = val;
+            key.val = val;
+        }
+    }
+
+    action.round();
+}
+
+fn divide(Component { output, int }: Component, v: f32) -> Component {
+    let int = match int {
+        Interpolation::Bezier([lx, ly, rx, ry]) => Interpolation::Bezier([lx, ly / v, rx, ry / v]),
+        Interpolation::Hermite([ix, iy, ox, oy]) => {
+            Interpolation::Hermite([ix, iy / v, ox, oy / v])
+        }
+        int => int,
+    };
+
+    Component {
+        output: output / v,
+        int,
+    }
+}
@@ -0,0 +1,170 @@
+use {
+    crate::{
+        action::{Action, Order, Transform},
+        params::Parameters,
+        parser,
+        skeleton::Skeleton,
+    },
+    glam::Quat,
+    serde::Serialize,
+    std::{
+        collections::{BTreeMap, HashMap},
+        error, fmt, ops,
+    },
+};
+
+/// An action sampled at a fixed rate.
+///
+/// Bones are identified by names or by skeleton indices.
+#[derive(Serialize)]
+pub struct Baked<B = String> {
+    fps: f32,
+    start: f32,
+    len: usize,
+    #[serde(skip_serializing_if = "ops::Not::not")]
+    additive: bool,
+    bones: BTreeMap<B, Vec<Transform>>,
+}
+
+impl<B> Baked<B> {
+    pub fn bones(&self) -> &BTreeMap<B, Vec<Transform>> {
+        &self.bones
+    }
+}
+
+impl Baked {
+    /// Replaces bone names with their indices in the skeleton.
+    ///
+    /// Bones missing from the skeleton are skipped, parsing with
+    /// [`skeleton`](crate::Options::skeleton) option reports them as warnings.
+    pub fn indexed(&self, sk: &Skeleton) -> Baked<u16> {
+        Baked {
+            fps: self.fps,
+            start: self.start,
+            len: self.len,
+            additive: self.additive,
+            bones: self
+                .bones
+                .iter()
+                .filter_map(|(bone, samples)| Some((sk.resolve(bone)?, samples.clone())))
+                .collect(),
+        }
+    }
+}
+
+/// Samples local transforms of all bones of the action at the given frame rate.
+///
+/// All bones share a single timeline from the first key of the action
+/// to the last one. Samples stay on the fixed-rate grid, so the last one
+/// may be past the last key. Euler rotations are converted to quaternions
+/// using rotation orders of bones from `orders`, XYZ by default.
+///
+/// # Errors
+/// Returns an error if the frame rate isn't positive or is too high.
+pub fn bake(
+    action: &Action,
+    fps: f32,
+    orders: &HashMap<String, Order>,
+) -> Result<Baked, InvalidFrameRate> {
+    parser::init_params();
+
+    let params = Parameters::get();
+    let (start, end) = action.range().unwrap_or_default();
+    let len = frames(start, end, fps)?;
+    let bones = action
+        .keyframes()
+        .keys()
+        .map(|bone| {
+            let order = orders.get(bone).copied().unwrap_or_default();
+            let mut near = Quat::IDENTITY;
+            let samples = (0..len)
+                .filter_map(|frame| {
+                    let input = start + frame as f32 / fps;
+                    let mut tr = action.transform_at(bone, input, order)?;
+
+                    // Keep quaternions in the same hemisphere to interpolate them
+                    let mut rot = Quat::from_array(tr.rot);
+                    if rot.dot(near) < 0. {
+                        rot = -rot;
+                    }
+
+                    near = rot;
+                    tr.pos = (params.pos_fn)(tr.pos);
+                    tr.rot = (params.rot_fn)(rot.to_array());
+                    tr.scale = (params.scl_fn)(tr.scale);
+                    Some(tr)
+                })
+                .collect();
+
+            (bone.clone(), samples)
+        })
+        .collect();
+
+    Ok(Baked {
+        fps,
+        start,
+        len,
+        additive: action.is_additive(),
+        bones,
+    })
+}
+
+/// The highest frame rate actions are sampled at.
+const MAX_FRAME_RATE: f32 = 1000.;
+
+/// Returns the number of samples at the frame rate covering the time range.
+pub(crate) fn frames(start: f32, end: f32, fps: f32) -> Result<usize, InvalidFrameRate> {
+    if !fps.is_finite() || fps <= 0. || fps > MAX_FRAME_RATE {
+        return Err(InvalidFrameRate(fps));
+    }
+
+    let len = ((end - start) * fps).ceil() as usize;
+    Ok(len.saturating_add(1))
+}
+
+#[derive(Debug)]
+pub struct InvalidFrameRate(pub f32);
+
+impl fmt::Display for InvalidFrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid frame rate {}, expected a positive one up to {MAX_FRAME_RATE}",
+            self.0,
+        )
+    }
+}
+
+impl error::Error for InvalidFrameRate {}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::action::{Channel, Component, Interpolation},
+    };
+
+    #[test]
+    fn samples() {
+        let key = |output| Component {
+            output,
+            int: Interpolation::Linear,
+        };
+
+        let mut action = Action::default();
+        action.insert_channel("bone".to_owned(), 0., Channel::LocationX(key(0.)));
+        action.insert_channel("bone".to_owned(), 0.9, Channel::LocationX(key(1.8)));
+
+        let baked = bake(&action, 2., &HashMap::new()).expect("baked");
+        let xs: Vec<_> = baked.bones()["bone"].iter().map(|tr| tr.pos[0]).collect();
+        assert_eq!(baked.len, 3, "the range is covered");
+        assert_eq!(xs, [0., 1., 1.8], "samples are on the grid");
+
+        for fps in [0., -1., f32::NAN, f32::INFINITY, 1e9] {
+            assert!(
+                bake(&action, fps, &HashMap::new()).is_err(),
+                "invalid frame rate {fps}",
+            );
+        }
+    }
+}
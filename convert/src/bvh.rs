@@ -0,0 +1,466 @@
+use {
+    crate::{
+        action::{Action, Axis, Channel, Component, Interpolation, Order},
+        bake,
+        params::Parameters,
+        parser,
+        skeleton::{Bone, Error as SkeletonError, Skeleton},
+    },
+    glam::{Quat, Vec3},
+    std::{
+        collections::HashMap,
+        fmt::{self, Write},
+        str::SplitWhitespace,
+    },
+};
+
+/// A skeleton with its motion read from a `.bvh` file.
+pub(crate) struct Bvh {
+    pub skeleton: Skeleton,
+    pub action: Action,
+    pub fps: f32,
+}
+
+/// Reads the hierarchy and the motion of a `.bvh` file.
+///
+/// Joints become bones with their offsets and the identity rest rotation.
+/// Each frame is written as a linear key with a quaternion rotation,
+/// joints with position channels also get location keys relative to their offsets.
+pub(crate) fn read(src: &str) -> Result<Bvh, Error> {
+    parser::init_params();
+
+    let mut tokens = Tokens(src.split_whitespace());
+    tokens.expect("HIERARCHY")?;
+
+    let mut skeleton = Skeleton::default();
+    let mut joints = vec![];
+    loop {
+        match tokens.next()? {
+            "ROOT" => read_joint(&mut tokens, None, &mut skeleton, &mut joints)?,
+            "MOTION" => break,
+            token => return Err(Error::Unexpected(token.to_owned())),
+        }
+    }
+
+    tokens.expect("Frames:")?;
+    let frames: usize = tokens.parse()?;
+    tokens.expect("Frame")?;
+    tokens.expect("Time:")?;
+    let frame_time: f32 = tokens.parse()?;
+    if !frame_time.is_finite() || frame_time <= 0. {
+        return Err(Error::FrameRate(1. / frame_time));
+    }
+
+    let mut action = Action::default();
+    let mut near = vec![Quat::IDENTITY; joints.len()];
+    for frame in 0..frames {
+        let input = frame as f32 * frame_time;
+        for (joint, near) in joints.iter().zip(&mut near) {
+            let offset = Vec3::from(joint.offset);
+            let mut pos = offset;
+            let mut rot = Quat::IDENTITY;
+            for &chan in &joint.channels {
+                let v: f32 = tokens.parse()?;
+                match chan {
+                    Dof::Position(axis) => pos[axis as usize] = v,
+                    Dof::Rotation(axis) => {
+                        rot *= Quat::from_axis_angle(axis.vec(), v.to_radians());
+                    }
+                }
+            }
+
+            // Keep quaternions in the same hemisphere to interpolate them
+            if rot.dot(*near) < 0. {
+                rot = -rot;
+            }
+
+            *near = rot;
+            let key = |output| Component {
+                output,
+                int: Interpolation::Linear,
+            };
+
+            let mut chans = vec![
+                Channel::QuaternionW(key(rot.w)),
+                Channel::QuaternionX(key(rot.x)),
+                Channel::QuaternionY(key(rot.y)),
+                Channel::QuaternionZ(key(rot.z)),
+            ];
+
+            if joint
+                .channels
+                .iter()
+                .any(|chan| matches!(chan, Dof::Position(_)))
+            {
+                let loc = pos - offset;
+                chans.extend([
+                    Channel::LocationX(key(loc.x)),
+                    Channel::LocationY(key(loc.y)),
+                    Channel::LocationZ(key(loc.z)),
+                ]);
+            }
+
+            for chan in chans {
+                action.insert_channel(joint.name.clone(), input, chan);
+            }
+        }
+    }
+
+    action.round();
+    Ok(Bvh {
+        skeleton,
+        action,
+        fps: 1. / frame_time,
+    })
+}
+
+struct Joint {
+    name: String,
+    offset: [f32; 3],
+    channels: Vec<Dof>,
+}
+
+/// A degree of freedom of a joint.
+#[derive(Clone, Copy)]
+enum Dof {
+    Position(Axis),
+    Rotation(Axis),
+}
+
+fn read_joint(
+    tokens: &mut Tokens,
+    parent: Option<u16>,
+    sk: &mut Skeleton,
+    joints: &mut Vec<Joint>,
+) -> Result<(), Error> {
+    let name = tokens.next()?.to_owned();
+    tokens.expect("{")?;
+    tokens.expect("OFFSET")?;
+    let offset = [tokens.parse()?, tokens.parse()?, tokens.parse()?];
+    tokens.expect("CHANNELS")?;
+    let len: usize = tokens.parse()?;
+    let channels = (0..len)
+        .map(|_| match tokens.next()? {
+            "Xposition" => Ok(Dof::Position(Axis::X)),
+            "Yposition" => Ok(Dof::Position(Axis::Y)),
+            "Zposition" => Ok(Dof::Position(Axis::Z)),
+            "Xrotation" => Ok(Dof::Rotation(Axis::X)),
+            "Yrotation" => Ok(Dof::Rotation(Axis::Y)),
+            "Zrotation" => Ok(Dof::Rotation(Axis::Z)),
+            chan => Err(Error::Channel(chan.to_owned())),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let params = Parameters::get();
+    let idx = sk.push(Bone {
+        name: name.clone(),
+        pos: (params.pos_fn)(offset),
+        rot: Quat::IDENTITY.to_array(),
+        scale: None,
+        parent,
+        bind: None,
+        inv_bind: None,
+    })?;
+
+    joints.push(Joint {
+        name,
+        offset,
+        channels,
+    });
+
+    loop {
+        match tokens.next()? {
+            "JOINT" => read_joint(tokens, Some(idx), sk, joints)?,
+            "End" => {
+                // End sites only mark the tip of the last bone
+                tokens.expect("Site")?;
+                tokens.expect("{")?;
+                tokens.expect("OFFSET")?;
+                for _ in 0..3 {
+                    let _: f32 = tokens.parse()?;
+                }
+
+                tokens.expect("}")?;
+            }
+            "}" => return Ok(()),
+            token => return Err(Error::Unexpected(token.to_owned())),
+        }
+    }
+}
+
+struct Tokens<'a>(SplitWhitespace<'a>);
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Result<&'a str, Error> {
+        self.0.next().ok_or(Error::UnexpectedEnd)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), Error> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(Error::Unexpected(token.to_owned())),
+        }
+    }
+
+    fn parse<T>(&mut self) -> Result<T, Error>
+    where
+        T: std::str::FromStr,
+    {
+        let token = self.next()?;
+        token.parse().map_err(|_| Error::Number(token.to_owned()))
+    }
+}
+
+/// Writes the skeleton and the action to `.bvh` format.
+///
+/// The action is sampled at the given frame rate. Rest rotations of bones are
+/// combined with animated ones, since `.bvh` joints only have offsets.
+/// Root bones and bones with animated locations get position channels.
+/// Euler rotations of the action are read using rotation orders of bones
+/// from `orders`, XYZ by default.
+///
+/// # Errors
+/// Returns an [error](Error) if the frame rate is invalid.
+pub fn write_bvh(
+    sk: &Skeleton,
+    action: &Action,
+    fps: f32,
+    orders: &HashMap<String, Order>,
+) -> Result<String, Error> {
+    let bones = sk.bones();
+    let mut tracks = vec![None; bones.len()];
+    for name in action.keyframes().keys() {
+        if let Some(idx) = sk.resolve(name) {
+            tracks[usize::from(idx)] = Some(name.as_str());
+        }
+    }
+
+    let mut children = vec![vec![]; bones.len()];
+    for (idx, bone) in bones.iter().enumerate() {
+        if let Some(parent) = bone.parent {
+            children[usize::from(parent)].push(idx);
+        }
+    }
+
+    let located: Vec<_> = bones
+        .iter()
+        .zip(&tracks)
+        .map(|(bone, track)| {
+            let keyed = |name| {
+                action.keyframes()[name]
+                    .iter()
+                    .any(|key| key.val.lx.or(key.val.ly).or(key.val.lz).is_some())
+            };
+
+            bone.parent.is_none() || track.is_some_and(keyed)
+        })
+        .collect();
+
+    let mut out = String::from("HIERARCHY\n");
+    let mut sequence = vec![];
+    let hierarchy = Hierarchy {
+        bones,
+        children: &children,
+        located: &located,
+    };
+
+    for (idx, bone) in bones.iter().enumerate() {
+        if bone.parent.is_none() {
+            hierarchy
+                .write(&mut out, idx, 0, &mut sequence)
+                .expect("write to string");
+        }
+    }
+
+    let (start, end) = action.range().unwrap_or_default();
+    let len = bake::frames(start, end, fps).map_err(|_| Error::FrameRate(fps))?;
+    let mut near = vec![[0.; 3]; bones.len()];
+    let _ = writeln!(out, "MOTION\nFrames: {len}\nFrame Time: {}", 1. / fps);
+    for frame in 0..len {
+        let input = start + frame as f32 / fps;
+        let mut values = vec![];
+        for &idx in &sequence {
+            let bone = &bones[idx];
+            let tr = tracks[idx].and_then(|name| {
+                let order = orders.get(name).copied().unwrap_or_default();
+                action.transform_at(name, input, order)
+            });
+
+            let (loc, rot) = tr.map_or((Vec3::ZERO, Quat::IDENTITY), |tr| {
+                (Vec3::from(tr.pos), Quat::from_array(tr.rot))
+            });
+
+            let rest = Quat::from_array(bone.rot);
+            if located[idx] {
+                let pos = Vec3::from(bone.pos) + rest * loc;
+                values.extend(pos.to_array());
+            }
+
+            // Channels are written in ZXY order, so Y is applied first
+            let [x, y, z] = Order::Yxz.to_euler(rest * rot, near[idx]);
+            near[idx] = [x, y, z];
+            values.extend([z, x, y].map(f32::to_degrees));
+        }
+
+        let values: Vec<_> = values.into_iter().map(|v| round(v).to_string()).collect();
+        let _ = writeln!(out, "{}", values.join(" "));
+    }
+
+    Ok(out)
+}
+
+struct Hierarchy<'a> {
+    bones: &'a [Bone],
+    children: &'a [Vec<usize>],
+    located: &'a [bool],
+}
+
+impl Hierarchy<'_> {
+    /// Writes the joint with its children and collects the order of joints.
+    fn write(
+        &self,
+        out: &mut String,
+        idx: usize,
+        depth: usize,
+        sequence: &mut Vec<usize>,
+    ) -> fmt::Result {
+        let indent = "\t".repeat(depth);
+        let bone = &self.bones[idx];
+        let kind = if bone.parent.is_none() {
+            "ROOT"
+        } else {
+            "JOINT"
+        };
+        let name: String = bone
+            .name
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+
+        let [x, y, z] = bone.pos.map(round);
+        writeln!(out, "{indent}{kind} {name}\n{indent}{{")?;
+        writeln!(out, "{indent}\tOFFSET {x} {y} {z}")?;
+        if self.located[idx] {
+            writeln!(
+                out,
+                "{indent}\tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation",
+            )?;
+        } else {
+            writeln!(out, "{indent}\tCHANNELS 3 Zrotation Xrotation Yrotation")?;
+        }
+
+        sequence.push(idx);
+        if self.children[idx].is_empty() {
+            writeln!(out, "{indent}\tEnd Site\n{indent}\t{{")?;
+            writeln!(out, "{indent}\t\tOFFSET 0 0 0\n{indent}\t}}")?;
+        }
+
+        for &child in &self.children[idx] {
+            self.write(out, child, depth + 1, sequence)?;
+        }
+
+        writeln!(out, "{indent}}}")
+    }
+}
+
+/// Rounds the value to 4 decimal places without producing negative zeros.
+fn round(v: f32) -> f32 {
+    (v * 1e4).round() / 1e4 + 0.
+}
+
+#[derive(Debug)]
+pub enum Error {
+    UnexpectedEnd,
+    Unexpected(String),
+    Number(String),
+    Channel(String),
+    FrameRate(f32),
+    Skeleton(SkeletonError),
+}
+
+impl From<SkeletonError> for Error {
+    fn from(v: SkeletonError) -> Self {
+        Self::Skeleton(v)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of file"),
+            Self::Unexpected(token) => write!(f, "unexpected token {token}"),
+            Self::Number(token) => write!(f, "invalid number {token}"),
+            Self::Channel(chan) => write!(f, "unknown channel {chan}"),
+            Self::FrameRate(fps) => write!(f, "invalid frame rate {fps}"),
+            Self::Skeleton(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "
+        HIERARCHY
+        ROOT hips
+        {
+            OFFSET 0 1 0
+            CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+            JOINT spine
+            {
+                OFFSET 0 0.5 0
+                CHANNELS 3 Zrotation Xrotation Yrotation
+                End Site
+                {
+                    OFFSET 0 0.5 0
+                }
+            }
+        }
+        MOTION
+        Frames: 2
+        Frame Time: 0.5
+        0 1 0 0 0 0 0 0 0
+        1 1 0 0 90 0 30 45 0
+    ";
+
+    #[test]
+    fn read_motion() {
+        let bvh = read(SRC).expect("bvh");
+        assert_eq!(bvh.fps, 2.);
+        assert_eq!(bvh.skeleton.bones().len(), 2);
+        assert_eq!(bvh.skeleton.bones()[1].parent, Some(0));
+
+        let hips = bvh.action.sample("hips", 0.5).expect("hips");
+        assert_eq!(hips.pos, [1., 0., 0.], "location relative to the offset");
+
+        let spine = bvh.action.sample("spine", 0.5).expect("spine");
+        let expected =
+            Quat::from_rotation_z(30_f32.to_radians()) * Quat::from_rotation_x(45_f32.to_radians());
+        let rot = Quat::from_array(spine.rot.to_quat(Order::default()));
+        assert!(
+            rot.abs_diff_eq(expected, 1e-3),
+            "rotations in channel order"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let bvh = read(SRC).expect("bvh");
+        let src = write_bvh(&bvh.skeleton, &bvh.action, bvh.fps, &HashMap::new()).expect("write");
+        let back = read(&src).expect("read written");
+        for bone in ["hips", "spine"] {
+            for time in [0., 0.5] {
+                let a = bvh.action.transform_at(bone, time, Order::default());
+                let b = back.action.transform_at(bone, time, Order::default());
+                let (a, b) = (a.expect("transform"), b.expect("transform"));
+                let (qa, qb) = (Quat::from_array(a.rot), Quat::from_array(b.rot));
+                assert!(
+                    qa.dot(qb).abs() > 0.9999,
+                    "same rotation of {bone} at {time}"
+                );
+                assert_eq!(a.pos, b.pos, "same location of {bone} at {time}");
+            }
+        }
+    }
+}
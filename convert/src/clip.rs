@@ -0,0 +1,213 @@
+use {
+    crate::action::{self, Action, Component, Interpolation},
+    glam::Vec2,
+    std::{collections::BTreeMap, error, fmt, str},
+};
+
+/// A named frame range of an action.
+#[derive(Clone)]
+pub struct Clip {
+    pub name: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl str::FromStr for Clip {
+    type Err = InvalidClip;
+
+    /// Parses a clip in the `name:start-end` format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, range) = s.rsplit_once(':').ok_or(InvalidClip)?;
+        let (start, end) = range.split_once('-').ok_or(InvalidClip)?;
+        let start: f32 = start.trim().parse().map_err(|_| InvalidClip)?;
+        let end: f32 = end.trim().parse().map_err(|_| InvalidClip)?;
+        if name.is_empty() || !start.is_finite() || !end.is_finite() || start >= end {
+            return Err(InvalidClip);
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            start,
+            end,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidClip;
+
+impl fmt::Display for InvalidClip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid clip, expected name:start-end")
+    }
+}
+
+impl error::Error for InvalidClip {}
+
+/// Cuts clips from each action.
+///
+/// Clip ranges are converted from frames to time with the frame rate.
+/// If there are several actions, clip names are prefixed with action names.
+pub(crate) fn cut_clips(
+    actions: BTreeMap<String, Action>,
+    clips: &[Clip],
+    fps: f32,
+) -> BTreeMap<String, Action> {
+    let single = actions.len() == 1;
+    let mut out = BTreeMap::new();
+    for (name, action) in actions {
+        for clip in clips {
+            let clip_name = if single {
+                clip.name.clone()
+            } else {
+                format!("{name}_{}", clip.name)
+            };
+
+            println!("cut {clip_name} from {name}");
+            out.insert(clip_name, cut(&action, clip.start / fps, clip.end / fps));
+        }
+    }
+
+    out
+}
+
+/// Cuts the time range from the action and rebases it to zero.
+///
+/// Keys are inserted at the cut points, bezier segments are split
+/// so the curves keep their shape.
+fn cut(action: &Action, start: f32, end: f32) -> Action {
+    let mut out = action.map_curves(|curve| {
+        cut_curve(&curve, start, end)
+            .into_iter()
+            .map(|(input, comp)| (input - start, comp))
+            .collect()
+    });
+
+    out.round();
+    out
+}
+
+fn cut_curve(curve: &[(f32, Component)], start: f32, end: f32) -> Vec<(f32, Component)> {
+    if curve.is_empty() {
+        return vec![];
+    }
+
+    let mut curve = curve.to_vec();
+    insert(&mut curve, start);
+    insert(&mut curve, end);
+    curve.retain(|&(input, _)| start <= input && input <= end);
+    curve
+}
+
+/// Inserts a key at the given time without changing the curve shape.
+fn insert(curve: &mut Vec<(f32, Component)>, input: f32) {
+    let idx = curve.partition_point(|&(x, _)| x < input);
+    if curve.get(idx).is_some_and(|&(x, _)| x == input) {
+        return;
+    }
+
+    // Values are held outside of the curve
+    let hold = |output| Component {
+        output,
+        int: Interpolation::Linear,
+    };
+
+    let (Some(&(x0, k0)), Some(&(x1, k1))) = (
+        idx.checked_sub(1).and_then(|idx| curve.get(idx)),
+        curve.get(idx),
+    ) else {
+        if idx == 0 {
+            let (_, first) = curve[0];
+            curve.insert(0, (input, hold(first.output)));
+        } else {
+            let (_, last) = curve.last_mut().expect("last key");
+            if last.int.handles().is_some() {
+                last.int = with_handles(last.int, None, Some(Vec2::ZERO));
+            }
+
+            let output = last.output;
+            curve.push((input, hold(output)));
+        }
+
+        return;
+    };
+
+    let output = action::eval_curve(&[(x0, k0), (x1, k1)], input).unwrap_or(k0.output);
+    let key = match k0.int {
+        Interpolation::Step | Interpolation::Linear => Component {
+            output,
+            int: k0.int,
+        },
+        Interpolation::Bezier(_) | Interpolation::Hermite(_) => {
+            let [_, _, rx, ry] = k0.int.handles().unwrap_or_default();
+            let [lx, ly, _, _] = k1.int.handles().unwrap_or_default();
+            let p0 = Vec2::new(x0, k0.output);
+            let p1 = Vec2::new((x0 + rx).clamp(x0, x1), k0.output + ry);
+            let p2 = Vec2::new((x1 + lx).clamp(x0, x1), k1.output + ly);
+            let p3 = Vec2::new(x1, k1.output);
+
+            // Split the segment by de Casteljau's algorithm
+            let t = action::solve_bezier([p0.x, p1.x, p2.x, p3.x], input);
+            let (p01, p12, p23) = (p0.lerp(p1, t), p1.lerp(p2, t), p2.lerp(p3, t));
+            let (p012, p123) = (p01.lerp(p12, t), p12.lerp(p23, t));
+            let p = p012.lerp(p123, t);
+
+            curve[idx - 1].1.int = with_handles(k0.int, None, Some(p01 - p0));
+            if k1.int.handles().is_some() {
+                curve[idx].1.int = with_handles(k1.int, Some(p23 - p3), None);
+            }
+
+            Component {
+                output: p.y,
+                int: with_handles(k0.int, Some(p012 - p), Some(p123 - p)),
+            }
+        }
+    };
+
+    curve.insert(idx, (input, key));
+}
+
+/// Replaces left or right handles of the interpolation.
+fn with_handles(int: Interpolation, left: Option<Vec2>, right: Option<Vec2>) -> Interpolation {
+    let [lx, ly, rx, ry] = int.handles().unwrap_or_default();
+    let [lx, ly] = left.map_or([lx, ly], |v| v.to_array());
+    let [rx, ry] = right.map_or([rx, ry], |v| v.to_array());
+    Interpolation::Bezier([lx, ly, rx, ry])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let clip: Clip = "walk:10-40".parse().expect("clip");
+        assert_eq!(clip.name, "walk");
+        assert_eq!((clip.start, clip.end), (10., 40.));
+        assert!("walk".parse::<Clip>().is_err(), "no range");
+        assert!("walk:40-10".parse::<Clip>().is_err(), "reversed range");
+    }
+
+    #[test]
+    fn cut_keeps_shape() {
+        let key = |output, int| Component { output, int };
+        let curve = [
+            (0., key(0., Interpolation::Bezier([0., 0., 0.5, 1.]))),
+            (2., key(4., Interpolation::Bezier([-0.5, 1., 0.5, -1.]))),
+            (4., key(0., Interpolation::Linear)),
+        ];
+
+        let (start, end) = (0.5, 3.);
+        let cut = cut_curve(&curve, start, end);
+        assert_eq!(cut.len(), 3, "boundary keys");
+        assert_eq!(cut.first().map(|&(x, _)| x), Some(start));
+        assert_eq!(cut.last().map(|&(x, _)| x), Some(end));
+
+        for i in 0..=10 {
+            let input = start + (end - start) * i as f32 / 10.;
+            let a = action::eval_curve(&curve, input).expect("value");
+            let b = action::eval_curve(&cut, input).expect("value");
+            assert!((a - b).abs() < 1e-3, "same value at {input}: {a} != {b}");
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use {
+    crc32fast::Hasher,
+    flate2::{bufread::MultiGzDecoder, read::DeflateDecoder},
+    quick_xml::{events::Event, Reader},
+    std::{
+        ffi::OsStr,
+        fmt,
+        fs::File,
+        io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+        path::Path,
+    },
+    zip::{result::ZipError, CompressionMethod, ZipArchive},
+};
+
+/// Opens the collada document of the file.
+///
+/// `.zae` archives and gzip compressed files are unpacked as they're read,
+/// decided by the extension or magic bytes. The root document of an archive
+/// is named by its manifest.
+///
+/// # Errors
+/// See [`Error`] type for details.
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>, Error> {
+    let mut file = BufReader::new(File::open(path)?);
+    let kind = match path.extension().and_then(OsStr::to_str) {
+        Some("zae") => Kind::Zip,
+        Some("gz") => Kind::Gzip,
+        _ => Kind::detect(&mut file)?,
+    };
+
+    match kind {
+        Kind::Plain => Ok(Box::new(file)),
+        Kind::Gzip => Ok(Box::new(BufReader::new(MultiGzDecoder::new(file)))),
+        Kind::Zip => unzip(file),
+    }
+}
+
+/// Unpacks the collada document from the source, decided by magic bytes.
+///
+/// An archive needs random access, so it's read in memory first.
+///
+/// # Errors
+/// See [`Error`] type for details.
+pub fn unpack<R>(mut src: R) -> Result<Box<dyn BufRead>, Error>
+where
+    R: BufRead + 'static,
+{
+    match Kind::detect(&mut src)? {
+        Kind::Plain => Ok(Box::new(src)),
+        Kind::Gzip => Ok(Box::new(BufReader::new(MultiGzDecoder::new(src)))),
+        Kind::Zip => {
+            let mut data = vec![];
+            src.read_to_end(&mut data)?;
+            unzip(Cursor::new(data))
+        }
+    }
+}
+
+enum Kind {
+    Plain,
+    Gzip,
+    Zip,
+}
+
+impl Kind {
+    fn detect<R>(src: &mut R) -> io::Result<Self>
+    where
+        R: BufRead,
+    {
+        let magic = src.fill_buf()?;
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Ok(Self::Gzip)
+        } else if magic.starts_with(b"PK\x03\x04") {
+            Ok(Self::Zip)
+        } else {
+            Ok(Self::Plain)
+        }
+    }
+}
+
+/// Streams the root document out of the archive.
+///
+/// The document is read straight from its place in the archive,
+/// so the archive isn't borrowed by the returned reader.
+/// Its checksum is verified when it's read to the end.
+fn unzip<R>(src: R) -> Result<Box<dyn BufRead>, Error>
+where
+    R: Read + Seek + 'static,
+{
+    let mut archive = ZipArchive::new(src)?;
+    let root = root_document(&mut archive)?;
+    let (start, size, method, crc) = {
+        let file = archive.by_name(&root)?;
+        (
+            file.data_start(),
+            file.compressed_size(),
+            file.compression(),
+            file.crc32(),
+        )
+    };
+
+    let mut src = archive.into_inner();
+    src.seek(SeekFrom::Start(start))?;
+    let data = src.take(size);
+    match method {
+        CompressionMethod::Stored => Ok(Box::new(BufReader::new(Checked::new(data, crc)))),
+        CompressionMethod::Deflated => {
+            let data = DeflateDecoder::new(data);
+            Ok(Box::new(BufReader::new(Checked::new(data, crc))))
+        }
+        _ => Err(Error::Compression(root)),
+    }
+}
+
+/// Computes the CRC-32 of the data as it's read,
+/// and fails at the end if it doesn't match the expected one.
+struct Checked<R> {
+    inner: R,
+    hasher: Hasher,
+    crc: u32,
+}
+
+impl<R> Checked<R> {
+    fn new(inner: R, crc: u32) -> Self {
+        Self {
+            inner,
+            hasher: Hasher::new(),
+            crc,
+        }
+    }
+}
+
+impl<R> Read for Checked<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() && self.hasher.clone().finalize() != self.crc {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the archived document is corrupted, its checksum doesn't match",
+            ));
+        }
+
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Returns the name of the root document from the `manifest.xml`,
+/// or the first `.dae` file if the archive has no manifest.
+fn root_document<R>(archive: &mut ZipArchive<R>) -> Result<String, Error>
+where
+    R: Read + Seek,
+{
+    let manifest = match archive.by_name("manifest.xml") {
+        Ok(mut file) => {
+            let mut manifest = String::new();
+            file.read_to_string(&mut manifest)?;
+            Some(manifest)
+        }
+        Err(ZipError::FileNotFound) => None,
+        Err(err) => return Err(Error::Zip(err)),
+    };
+
+    let root = match manifest {
+        Some(manifest) => read_manifest(&manifest),
+        None => archive
+            .file_names()
+            .find(|name| name.to_ascii_lowercase().ends_with(".dae"))
+            .map(str::to_owned),
+    };
+
+    root.ok_or(Error::NoDocument)
+}
+
+/// Reads the `<dae_root>` path of the manifest.
+fn read_manifest(manifest: &str) -> Option<String> {
+    let mut reader = Reader::from_str(manifest);
+    let mut root = false;
+    loop {
+        match reader.read_event().ok()? {
+            Event::Start(e) => root = e.name().as_ref() == b"dae_root",
+            Event::Text(e) if root => {
+                let uri = e.unescape().ok()?;
+                let path = uri.trim().trim_start_matches("./");
+                return Some(decode_uri(path));
+            }
+            Event::Eof => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Decodes percent-encoded bytes of the URI path.
+fn decode_uri(path: &str) -> String {
+    let mut out = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(b) = bytes.next() {
+        let hex = |b: Option<u8>| char::from(b?).to_digit(16);
+        if b == b'%' {
+            let mut rest = bytes.clone();
+            if let (Some(hi), Some(lo)) = (hex(rest.next()), hex(rest.next())) {
+                out.push((hi * 16 + lo) as u8);
+                bytes = rest;
+                continue;
+            }
+        }
+
+        out.push(b);
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Zip(ZipError),
+    NoDocument,
+    Compression(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(v: ZipError) -> Self {
+        Self::Zip(v)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Zip(err) => write!(f, "{err}"),
+            Self::NoDocument => write!(f, "no collada document found in the archive"),
+            Self::Compression(name) => {
+                write!(f, "unsupported compression method of the document {name:?}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        flate2::{write::GzEncoder, Compression},
+        std::io::Write,
+        zip::{write::SimpleFileOptions, ZipWriter},
+    };
+
+    const DOC: &str = "<COLLADA></COLLADA>";
+
+    fn read(src: Vec<u8>) -> String {
+        let mut out = String::new();
+        unpack(Cursor::new(src))
+            .and_then(|mut doc| Ok(doc.read_to_string(&mut out)?))
+            .expect("unpacked document");
+
+        out
+    }
+
+    #[test]
+    fn containers() {
+        assert_eq!(read(DOC.into()), DOC, "plain document");
+
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(DOC.as_bytes()).expect("write");
+        assert_eq!(read(gz.finish().expect("gzip")), DOC, "gzip document");
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let opts = SimpleFileOptions::default();
+        zip.start_file("other.dae", opts).expect("file");
+        zip.write_all(b"<other/>").expect("write");
+        zip.start_file("models/hero model.dae", opts).expect("file");
+        zip.write_all(DOC.as_bytes()).expect("write");
+        zip.start_file("manifest.xml", opts).expect("file");
+        zip.write_all(b"<dae_root>./models/hero%20model.dae</dae_root>")
+            .expect("write");
+
+        let zae = zip.finish().expect("zip").into_inner();
+        assert_eq!(read(zae), DOC, "root document of the archive");
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let opts = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("model.dae", opts).expect("file");
+        zip.write_all(DOC.as_bytes()).expect("write");
+
+        let mut zae = zip.finish().expect("zip").into_inner();
+        let at = zae
+            .windows(DOC.len())
+            .position(|data| data == DOC.as_bytes())
+            .expect("stored document");
+
+        zae[at + 1] = b'X';
+        let mut out = String::new();
+        let res = unpack(Cursor::new(zae)).and_then(|mut doc| Ok(doc.read_to_string(&mut out)?));
+        assert!(res.is_err(), "the checksum doesn't match");
+    }
+}
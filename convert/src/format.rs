@@ -5,7 +5,9 @@ use {
     },
     std::{
         borrow::Cow,
-        fmt, mem,
+        fmt,
+        io::{self, BufRead, Read},
+        mem,
         str::{self, FromStr, Utf8Error},
         string::FromUtf8Error,
     },
@@ -13,15 +15,22 @@ use {
 
 #[derive(Default)]
 pub(crate) struct Document {
-    pub geometry: Vec<Geometry>,
     pub nodes: Vec<Node>,
     pub animations: Vec<Animation>,
+    pub skins: Vec<Skin>,
+    pub clips: Vec<Clip>,
+
+    /// Missing optional attributes, which are read with defaults.
+    pub warnings: Vec<Problem>,
 }
 
 pub(crate) struct Geometry {
     pub id: String,
     pub name: String,
     pub sources: Vec<Source>,
+
+    /// Inputs of the `<vertices>` element referenced by the `VERTEX` input of triangles.
+    pub vertices: Vec<Input>,
     pub triangles: Triangles,
 }
 
@@ -31,6 +40,7 @@ pub(crate) struct Triangles {
 }
 
 pub(crate) struct Input {
+    pub semantic: String,
     pub source: String,
     pub offset: usize,
 }
@@ -39,11 +49,79 @@ pub(crate) struct Source {
     pub id: String,
     pub floats: Vec<f32>,
     pub names: Vec<Name>,
+    pub idents: Vec<String>,
+    pub accessor: Option<Accessor>,
+}
+
+impl Source {
+    /// Returns the number of elements in the source.
+    pub fn count(&self) -> usize {
+        match &self.accessor {
+            Some(acc) => acc.count,
+            None => self.floats.len(),
+        }
+    }
+
+    /// Returns the first value of the element at the index.
+    pub fn value(&self, idx: usize) -> Option<f32> {
+        let start = match &self.accessor {
+            Some(acc) => acc.offset + idx * acc.stride,
+            None => idx,
+        };
+
+        self.floats.get(start).copied()
+    }
+
+    /// Returns values of the named params of the element at the index.
+    ///
+    /// Params missing from the accessor are read by their position,
+    /// and without an accessor elements are tightly packed.
+    pub fn get<const N: usize>(&self, idx: usize, names: [&str; N]) -> Option<[f32; N]> {
+        let (start, params) = match &self.accessor {
+            Some(acc) => (acc.offset + idx * acc.stride, &acc.params[..]),
+            None => (idx * N, &[][..]),
+        };
+
+        let mut out = [0.; N];
+        for (pos, (v, name)) in out.iter_mut().zip(names).enumerate() {
+            let pos = params.iter().position(|param| param == name).unwrap_or(pos);
+            *v = *self.floats.get(start + pos)?;
+        }
+
+        Some(out)
+    }
+}
+
+/// Describes how elements are laid out in the source array.
+pub(crate) struct Accessor {
+    pub count: usize,
+    pub offset: usize,
+    pub stride: usize,
+
+    /// Param names, unnamed params are empty but still take their place.
+    pub params: Vec<String>,
+}
+
+/// Finds the source referenced by the input with the semantic.
+pub(crate) fn find_source<'a>(
+    inputs: &[Input],
+    sources: &'a [Source],
+    semantic: &str,
+) -> Option<&'a Source> {
+    let input = find_input(inputs, semantic)?;
+    let id = input.source.strip_prefix('#').unwrap_or(&input.source);
+    sources.iter().find(|source| source.id == id)
+}
+
+/// Finds the first input with the semantic.
+pub(crate) fn find_input<'a>(inputs: &'a [Input], semantic: &str) -> Option<&'a Input> {
+    inputs.iter().find(|input| input.semantic == semantic)
 }
 
 pub(crate) struct Node {
     pub id: String,
     pub name: String,
+    pub sid: Option<String>,
     pub ty: String,
     pub mat: Vec<f32>,
     pub children: Vec<Self>,
@@ -52,12 +130,29 @@ pub(crate) struct Node {
 pub(crate) struct Animation {
     pub id: String,
     pub name: String,
+    pub parent: Option<String>,
+    pub target: Option<String>,
+    pub sources: Vec<Source>,
+    pub inputs: Vec<Input>,
+}
+
+pub(crate) struct Clip {
+    pub name: String,
+    pub animations: Vec<String>,
+}
+
+pub(crate) struct Skin {
     pub sources: Vec<Source>,
+    pub joints: Vec<Input>,
 }
 
 pub(crate) enum Name {
     Linear,
     Bezier,
+    Step,
+    Hermite,
+    Cardinal,
+    Bspline,
 }
 
 impl Name {
@@ -65,31 +160,278 @@ impl Name {
         match s {
             "LINEAR" => Ok(Self::Linear),
             "BEZIER" => Ok(Self::Bezier),
+            "STEP" | "CONSTANT" => Ok(Self::Step),
+            "HERMITE" => Ok(Self::Hermite),
+            "CARDINAL" => Ok(Self::Cardinal),
+            "BSPLINE" => Ok(Self::Bspline),
             _ => Err(Error::Name(s.to_owned())),
         }
     }
 }
 
-pub(crate) fn read(src: &str) -> Result<Document, Failed> {
-    let mut reader = Reader::from_str(src);
-    read_from_reader(&mut reader).map_err(|err| {
-        let mut pos = reader.buffer_position();
-        let mut line = 1;
-        for line_len in src.lines().map(str::len) {
-            match pos.checked_sub(line_len) {
-                Some(p) => pos = p,
-                None => break,
-            }
+/// Receives geometries of the document as they are read.
+pub(crate) struct Geometries<'a> {
+    /// Checks if the geometry with the name is needed,
+    /// others are skipped without being parsed.
+    pub selects: &'a dyn Fn(&str) -> bool,
+
+    /// Takes the read geometry.
+    pub take: &'a mut dyn FnMut(Geometry),
+}
+
+/// Reads the document from the source as it streams.
+///
+/// Geometries are passed on as soon as they are read instead
+/// of being kept in the document. Without the receiver they are skipped.
+pub(crate) fn read<R>(src: R, mut geometry: Option<Geometries>) -> Result<Document, Failed>
+where
+    R: BufRead,
+{
+    let mut reader = Reader::from_reader(Lines {
+        inner: src,
+        pos: 0,
+        breaks: vec![],
+    });
+
+    let mut reading = Reading::default();
+    let doc = read_from_reader(&mut reader, &mut reading, &mut geometry)
+        .map_err(|err| reading.report(err, reading.pos))
+        .ok();
+
+    match doc {
+        Some(mut doc) if reading.problems.is_empty() => {
+            doc.warnings = reading.warnings;
+            Ok(doc)
+        }
+        _ => Err(Failed {
+            problems: reading.problems,
+        }),
+    }
+}
+
+/// Passes the source through and records positions of line breaks,
+/// so the whole document is never kept to find places of problems.
+struct Lines<R> {
+    inner: R,
+    pos: usize,
+    breaks: Vec<usize>,
+}
+
+impl<R> Lines<R> {
+    fn record(pos: &mut usize, breaks: &mut Vec<usize>, bytes: &[u8]) {
+        let lf = bytes.iter().enumerate().filter(|&(_, &b)| b == b'\n');
+        breaks.extend(lf.map(|(idx, _)| *pos + idx));
+        *pos += bytes.len();
+    }
+}
+
+impl<R> Read for Lines<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        Self::record(&mut self.pos, &mut self.breaks, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R> BufRead for Lines<R>
+where
+    R: BufRead,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Consumed bytes are still in the buffer
+        if let Ok(buf) = self.inner.fill_buf() {
+            let consumed = &buf[..amt.min(buf.len())];
+            Self::record(&mut self.pos, &mut self.breaks, consumed);
+        }
+
+        self.inner.consume(amt);
+    }
+}
+
+/// Keeps track of the place in the document and collects problems.
+#[derive(Default)]
+struct Reading {
+    /// The start of the current event.
+    pos: usize,
+
+    /// The number of lines before the current event and the start of the last one.
+    lines: usize,
+    line_start: usize,
+
+    /// Line breaks within the current event.
+    breaks: Vec<usize>,
+
+    /// The markup of the current tag.
+    tag: Vec<u8>,
 
-            line += 1;
+    elements: Vec<String>,
+    problems: Vec<Problem>,
+    warnings: Vec<Problem>,
+}
+
+impl Reading {
+    /// Moves to the next event with its line breaks.
+    fn next(&mut self, breaks: Vec<usize>) {
+        let prev = mem::replace(&mut self.breaks, breaks);
+        if let Some(&last) = prev.last() {
+            self.lines += prev.len();
+            self.line_start = last + 1;
+        }
+    }
+
+    /// Returns the path of the current element without the root one.
+    fn path(&self) -> String {
+        let elements: Vec<_> = self.elements.iter().skip(1).map(String::as_str).collect();
+        elements.join("/")
+    }
+
+    /// Reports the problem in the current tag.
+    fn report(&mut self, err: Error, pos: usize) {
+        let tag = mem::take(&mut self.tag);
+        self.report_in(err, pos, &tag, self.pos);
+        self.tag = tag;
+    }
+
+    /// Reports the problem, the snippet is cut from the text at the position.
+    fn report_in(&mut self, err: Error, pos: usize, text: &[u8], text_pos: usize) {
+        let problem = self.locate(err, pos, text, text_pos);
+        self.problems.push(problem);
+    }
+
+    /// Returns the problem at the position of the text.
+    fn locate(&self, err: Error, pos: usize, text: &[u8], text_pos: usize) -> Problem {
+        // Long lines, such as arrays, are cut around the problem
+        const AROUND: usize = 40;
+
+        let n = self.breaks.partition_point(|&idx| idx < pos);
+        let line_start = match n {
+            0 => self.line_start,
+            n => self.breaks[n - 1] + 1,
+        };
+
+        let at = pos.saturating_sub(text_pos).min(text.len());
+        let (before, after) = text.split_at(at);
+        let start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |idx| idx + 1);
+        let start = start.max(before.len().saturating_sub(AROUND * 4));
+        let end = after
+            .iter()
+            .take(AROUND * 4)
+            .position(|&b| b == b'\r' || b == b'\n')
+            .unwrap_or(after.len().min(AROUND * 4));
+
+        let head: Vec<_> = String::from_utf8_lossy(&before[start..]).chars().collect();
+        let head: String = head[head.len().saturating_sub(AROUND)..].iter().collect();
+        let head = head.trim_start();
+        let tail: String = String::from_utf8_lossy(&after[..end])
+            .chars()
+            .take(AROUND)
+            .collect();
+
+        Problem {
+            err,
+            line: self.lines + n + 1,
+            col: pos - line_start + 1,
+            path: self.path(),
+            snippet: format!("{head}{tail}"),
+            snippet_col: head.chars().count() + 1,
+        }
+    }
+
+    /// Returns the value, or reports the problem at the current event
+    /// and keeps reading with the default value.
+    fn check<T>(&mut self, res: Result<T, Error>) -> T
+    where
+        T: Default,
+    {
+        res.unwrap_or_else(|err| {
+            self.report(err, self.pos);
+            T::default()
+        })
+    }
+
+    /// Returns the value of an optional attribute, or warns about it
+    /// at the current event and keeps reading with the default value.
+    fn optional(&mut self, res: Result<String, Error>, default: &str) -> String {
+        res.unwrap_or_else(|err| {
+            let problem = self.locate(err, self.pos, &self.tag, self.pos);
+            self.warnings.push(problem);
+            default.to_owned()
+        })
+    }
+
+    /// Parses whitespace separated values of the current text.
+    fn parse<T>(&mut self, text: &[u8], values: &mut Vec<T>)
+    where
+        T: FromStr,
+    {
+        self.parse_with(text, values, |token| {
+            let token = str::from_utf8(token)?;
+            token.parse().map_err(|_| Error::Parse(token.to_owned()))
+        });
+    }
+
+    /// Parses whitespace separated floats of the current text.
+    ///
+    /// Floats make up most of a document, so they're parsed from bytes
+    /// with a faster parser.
+    fn parse_floats(&mut self, text: &[u8], values: &mut Vec<f32>) {
+        self.parse_with(text, values, |token| {
+            fast_float2::parse(token)
+                .map_err(|_| Error::Parse(String::from_utf8_lossy(token).into_owned()))
+        });
+    }
+
+    fn parse_with<T, F>(&mut self, text: &[u8], values: &mut Vec<T>, f: F)
+    where
+        F: Fn(&[u8]) -> Result<T, Error>,
+    {
+        let tokens = text
+            .split(u8::is_ascii_whitespace)
+            .filter(|token| !token.is_empty());
+
+        for token in tokens {
+            match f(token) {
+                Ok(v) => values.push(v),
+                Err(err) => {
+                    let offset = token.as_ptr() as usize - text.as_ptr() as usize;
+                    self.report_in(err, self.pos + offset, text, self.pos);
+                }
+            }
         }
+    }
+}
 
-        Failed { err, line }
-    })
+/// Returns the element name with its id, if it has one.
+fn element(e: &BytesStart) -> String {
+    let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+    match e.get_attribute_as_string("id") {
+        Ok(id) => format!("{name}[id={id}]"),
+        Err(_) => name,
+    }
 }
 
+/// Reads the document, reporting problems with values to continue reading.
+///
+/// Returns an error if the structure of the document is broken.
 #[allow(clippy::too_many_lines)]
-fn read_from_reader(reader: &mut Reader<&[u8]>) -> Result<Document, Error> {
+fn read_from_reader<R>(
+    reader: &mut Reader<Lines<R>>,
+    r: &mut Reading,
+    geometry: &mut Option<Geometries>,
+) -> Result<Document, Error>
+where
+    R: BufRead,
+{
     use events::Event;
 
     enum Library {
@@ -97,64 +439,126 @@ fn read_from_reader(reader: &mut Reader<&[u8]>) -> Result<Document, Error> {
         Geometries,
         VisualScenes,
         Animations,
+        Controllers,
+        AnimationClips,
     }
 
     let mut library = Library::None;
     let mut doc = Document::default();
 
     let mut sources = vec![];
+    let mut vertices = vec![];
     let mut indxs = vec![];
     let mut inputs = vec![];
 
     let mut stack = vec![];
+    let mut buf = vec![];
+    let mut skipped = vec![];
     loop {
-        match reader.read_event() {
+        r.pos = reader.buffer_position();
+        buf.clear();
+        let event = reader.read_event_into(&mut buf);
+        r.next(mem::take(&mut reader.get_mut().breaks));
+        r.tag.clear();
+        match &event {
+            Ok(Event::Start(e)) => r.tag.extend([b"<", &e[..], b">"].concat()),
+            Ok(Event::Empty(e)) => r.tag.extend([b"<", &e[..], b"/>"].concat()),
+            Ok(Event::End(e)) => r.tag.extend([b"</", &e[..], b">"].concat()),
+            _ => {}
+        }
+
+        if let Ok(Event::Start(e) | Event::Empty(e)) = &event {
+            r.elements.push(element(e));
+        }
+
+        let closed = matches!(event, Ok(Event::End(_) | Event::Empty(_)));
+        match event {
             Ok(Event::Start(e)) => match e.name().as_ref() {
-                b"library_geometries" => library = Library::Geometries,
+                b"library_geometries" if geometry.is_some() => library = Library::Geometries,
                 b"library_visual_scenes" => library = Library::VisualScenes,
                 b"library_animations" => library = Library::Animations,
+                b"library_controllers" => library = Library::Controllers,
+                b"library_animation_clips" => library = Library::AnimationClips,
                 b"geometry" => {
-                    if let Library::Geometries = library {
-                        stack.push(El::Geometry {
-                            id: e.get_attribute_as_string("id")?,
-                            name: e.get_attribute_as_string("name")?,
-                        });
+                    if let (Library::Geometries, Some(geometry)) = (&library, &geometry) {
+                        let id = r.check(e.get_attribute_as_string("id"));
+                        let name = r.optional(e.get_attribute_as_string("name"), &id);
+                        if (geometry.selects)(&name) {
+                            stack.push(El::Geometry { id, name });
+                        } else {
+                            skipped.clear();
+                            reader
+                                .read_to_end_into(e.name(), &mut skipped)
+                                .map_err(Error::XmlError)?;
+                            r.elements.pop();
+                        }
                     }
                 }
                 b"source" => {
-                    if let Library::Geometries | Library::Animations = library {
+                    if let Library::Geometries | Library::Animations | Library::Controllers =
+                        library
+                    {
                         stack.push(El::Source {
-                            id: e.get_attribute_as_string("id")?,
+                            id: r.check(e.get_attribute_as_string("id")),
                         });
                     }
                 }
                 b"float_array" => {
-                    if let Library::Geometries | Library::Animations = library {
-                        let count = e.get_attribute_as_parsed("count")?;
+                    if let Library::Geometries | Library::Animations | Library::Controllers =
+                        library
+                    {
+                        let count = r.check(e.get_attribute_as_parsed("count"));
                         let floats = Vec::with_capacity(count);
                         stack.push(El::FloatArray { floats });
                     }
                 }
                 b"Name_array" => {
-                    if let Library::Animations = library {
-                        let count = e.get_attribute_as_parsed("count")?;
+                    if let Library::Animations | Library::Controllers = library {
+                        let count = r.check(e.get_attribute_as_parsed("count"));
                         let names = Vec::with_capacity(count);
                         stack.push(El::NameArray { names });
                     }
                 }
+                b"accessor" => {
+                    if let Library::Geometries | Library::Animations | Library::Controllers =
+                        library
+                    {
+                        stack.push(El::Accessor(Accessor {
+                            count: r.check(e.get_attribute_as_parsed("count")),
+                            offset: e.get_attribute_as_parsed("offset").unwrap_or(0),
+                            stride: e.get_attribute_as_parsed("stride").unwrap_or(1),
+                            params: vec![],
+                        }));
+                    }
+                }
+                b"vertices" => {
+                    if let Library::Geometries = library {
+                        stack.push(El::Vertices);
+                    }
+                }
+                b"sampler" => {
+                    if let Library::Animations = library {
+                        stack.push(El::Sampler);
+                    }
+                }
                 b"triangles" => {
                     if let Library::Geometries = library {
-                        let count = e.get_attribute_as_parsed("count")?;
+                        let count = r.check(e.get_attribute_as_parsed("count"));
                         let indxs = Vec::with_capacity(count);
                         stack.push(El::Triangles { indxs });
                     }
                 }
                 b"node" => {
                     if let Library::VisualScenes = library {
+                        let id = r.check(e.get_attribute_as_string("id"));
+                        let name = r.optional(e.get_attribute_as_string("name"), &id);
                         stack.push(El::Node(Node {
-                            id: e.get_attribute_as_string("id")?,
-                            name: e.get_attribute_as_string("name")?,
-                            ty: e.get_attribute_as_string("type")?,
+                            id,
+                            name,
+                            sid: e.get_attribute_as_string("sid").ok(),
+                            ty: e
+                                .get_attribute_as_string("type")
+                                .unwrap_or_else(|_| "NODE".to_owned()),
                             mat: vec![],
                             children: vec![],
                         }));
@@ -167,16 +571,42 @@ fn read_from_reader(reader: &mut Reader<&[u8]>) -> Result<Document, Error> {
                 }
                 b"animation" => {
                     if let Library::Animations = library {
+                        let id = r.check(e.get_attribute_as_string("id"));
+                        let name = r.optional(e.get_attribute_as_string("name"), &id);
                         stack.push(El::Animation {
-                            id: e.get_attribute_as_string("id")?,
-                            name: e.get_attribute_as_string("name")?,
+                            id,
+                            name,
+                            target: None,
                         });
                     }
                 }
+                b"skin" => {
+                    if let Library::Controllers = library {
+                        stack.push(El::Skin);
+                    }
+                }
+                b"animation_clip" => {
+                    if let Library::AnimationClips = library {
+                        let id = e.get_attribute_as_string("id").unwrap_or_default();
+                        stack.push(El::Clip {
+                            name: r.optional(e.get_attribute_as_string("name"), &id),
+                            animations: vec![],
+                        });
+                    }
+                }
+                b"joints" => {
+                    if let Library::Controllers = library {
+                        stack.push(El::Joints);
+                    }
+                }
                 _ => {}
             },
             Ok(Event::End(e)) => match e.name().as_ref() {
-                b"library_geometries" | b"library_visual_scenes" | b"library_animations" => {
+                b"library_geometries"
+                | b"library_visual_scenes"
+                | b"library_animations"
+                | b"library_controllers"
+                | b"library_animation_clips" => {
                     library = Library::None;
                 }
                 b"geometry" => {
@@ -185,19 +615,26 @@ fn read_from_reader(reader: &mut Reader<&[u8]>) -> Result<Document, Error> {
                             return Err(Error::UnexpectedClosingTag("geometry".to_owned()));
                         };
 
-                        doc.geometry.push(Geometry {
+                        let geom = Geometry {
                             id,
                             name,
                             sources: mem::take(&mut sources),
+                            vertices: mem::take(&mut vertices),
                             triangles: Triangles {
                                 indxs: mem::take(&mut indxs),
                                 inputs: mem::take(&mut inputs),
                             },
-                        });
+                        };
+
+                        if let Some(geometry) = geometry {
+                            (geometry.take)(geom);
+                        }
                     }
                 }
                 b"source" => {
-                    if let Library::Geometries | Library::Animations = library {
+                    if let Library::Geometries | Library::Animations | Library::Controllers =
+                        library
+                    {
                         let Some(El::Source { id }) = stack.pop() else {
                             return Err(Error::UnexpectedClosingTag("source".to_owned()));
                         };
@@ -208,7 +645,9 @@ fn read_from_reader(reader: &mut Reader<&[u8]>) -> Result<Document, Error> {
                     }
                 }
                 b"float_array" => {
-                    if let Library::Geometries | Library::Animations = library {
+                    if let Library::Geometries | Library::Animations | Library::Controllers =
+                        library
+                    {
                         let Some(El::FloatArray { floats }) = stack.pop() else {
                             return Err(Error::UnexpectedClosingTag("float_array".to_owned()));
                         };
@@ -217,22 +656,68 @@ fn read_from_reader(reader: &mut Reader<&[u8]>) -> Result<Document, Error> {
                             id: String::new(),
                             floats,
                             names: vec![],
+                            idents: vec![],
+                            accessor: None,
                         });
                     }
                 }
                 b"Name_array" => {
-                    if let Library::Animations = library {
-                        let Some(El::NameArray { names }) = stack.pop() else {
+                    if let Library::Animations | Library::Controllers = library {
+                        let Some(El::NameArray { names: idents }) = stack.pop() else {
                             return Err(Error::UnexpectedClosingTag("Name_array".to_owned()));
                         };
 
-                        sources.push(Source {
-                            id: String::new(),
-                            floats: vec![],
-                            names,
+                        // Unknown names are reported and skipped when reading the text
+                        sources.push(match library {
+                            Library::Animations => Source {
+                                id: String::new(),
+                                floats: vec![],
+                                names: idents
+                                    .iter()
+                                    .filter_map(|n| Name::from_str(n).ok())
+                                    .collect(),
+                                idents: vec![],
+                                accessor: None,
+                            },
+                            _ => Source {
+                                id: String::new(),
+                                floats: vec![],
+                                names: vec![],
+                                idents,
+                                accessor: None,
+                            },
                         });
                     }
                 }
+                b"accessor" => {
+                    if let Library::Geometries | Library::Animations | Library::Controllers =
+                        library
+                    {
+                        let Some(El::Accessor(accessor)) = stack.pop() else {
+                            return Err(Error::UnexpectedClosingTag("accessor".to_owned()));
+                        };
+
+                        if let Some(source) = sources.last_mut() {
+                            source.accessor = Some(accessor);
+                        }
+                    }
+                }
+                b"vertices" => {
+                    if let Library::Geometries = library {
+                        let Some(El::Vertices) = stack.pop() else {
+                            return Err(Error::UnexpectedClosingTag("vertices".to_owned()));
+                        };
+
+                        vertices = mem::take(&mut inputs);
+                    }
+                }
+                b"sampler" => {
+                    if let Library::Animations = library {
+                        let Some(El::Sampler) = stack.pop() else {
+                            return Err(Error::UnexpectedClosingTag("sampler".to_owned()));
+                        };
+                    }
+                }
                 b"triangles" => {
                     if let Library::Geometries = library {
                         let Some(El::Triangles { indxs: i }) = stack.pop() else {
@@ -264,80 +749,172 @@ fn read_from_reader(reader: &mut Reader<&[u8]>) -> Result<Document, Error> {
                 }
                 b"animation" => {
                     if let Library::Animations = library {
-                        let Some(El::Animation { id, name }) = stack.pop() else {
+                        let Some(El::Animation { id, name, target }) = stack.pop() else {
                             return Err(Error::UnexpectedClosingTag("animation".to_owned()));
                         };
 
+                        let parent = match stack.last() {
+                            Some(El::Animation { id, .. }) => Some(id.clone()),
+                            _ => None,
+                        };
+
                         doc.animations.push(Animation {
                             id,
                             name,
+                            parent,
+                            target,
                             sources: mem::take(&mut sources),
+                            inputs: mem::take(&mut inputs),
                         });
                     }
                 }
-                _ => {}
-            },
-            Ok(Event::Empty(e)) => {
-                let Some(El::Triangles { .. }) = stack.last() else {
-                    continue;
-                };
-
-                inputs.push(Input {
-                    source: e.get_attribute_as_string("source")?,
-                    offset: e.get_attribute_as_parsed("offset")?,
-                });
-            }
-            Ok(Event::Text(e)) => match stack.last_mut() {
-                Some(El::FloatArray { floats, .. }) => {
-                    let e = str::from_utf8(&e)?;
-                    for f in e.split_whitespace() {
-                        let f = f.parse().map_err(|_| Error::Parse(f.to_owned()))?;
-                        floats.push(f);
+                b"skin" => {
+                    if let Library::Controllers = library {
+                        let Some(El::Skin) = stack.pop() else {
+                            return Err(Error::UnexpectedClosingTag("skin".to_owned()));
+                        };
+
+                        doc.skins.push(Skin {
+                            sources: mem::take(&mut sources),
+                            joints: mem::take(&mut inputs),
+                        });
                     }
                 }
-                Some(El::Triangles { indxs }) => {
-                    let e = str::from_utf8(&e)?;
-                    for i in e.split_whitespace() {
-                        let i = i.parse().map_err(|_| Error::Parse(i.to_owned()))?;
-                        indxs.push(i);
+                b"joints" => {
+                    if let Library::Controllers = library {
+                        let Some(El::Joints) = stack.pop() else {
+                            return Err(Error::UnexpectedClosingTag("joints".to_owned()));
+                        };
                     }
                 }
+                b"animation_clip" => {
+                    if let Library::AnimationClips = library {
+                        let Some(El::Clip { name, animations }) = stack.pop() else {
+                            return Err(Error::UnexpectedClosingTag("animation_clip".to_owned()));
+                        };
+
+                        doc.clips.push(Clip { name, animations });
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Empty(e)) => match stack.last_mut() {
+                Some(El::Triangles { .. }) => inputs.push(Input {
+                    semantic: r.check(e.get_attribute_as_string("semantic")),
+                    source: r.check(e.get_attribute_as_string("source")),
+                    offset: r.check(e.get_attribute_as_parsed("offset")),
+                }),
+                Some(El::Joints | El::Vertices | El::Sampler) => inputs.push(Input {
+                    semantic: r.check(e.get_attribute_as_string("semantic")),
+                    source: r.check(e.get_attribute_as_string("source")),
+                    offset: 0,
+                }),
+                Some(El::Accessor(Accessor { params, .. })) => {
+                    params.push(e.get_attribute_as_string("name").unwrap_or_default());
+                }
+                Some(El::Animation { target, .. }) if e.name().as_ref() == b"channel" => {
+                    *target = Some(r.check(e.get_attribute_as_string("target")));
+                }
+                Some(El::Clip { animations, .. }) => {
+                    let url = r.check(e.get_attribute_as_string("url"));
+                    let id = url.strip_prefix('#').map_or(url.as_str(), |id| id);
+                    animations.push(id.to_owned());
+                }
+                _ => {}
+            },
+            Ok(Event::Text(e)) => match stack.last_mut() {
+                Some(El::FloatArray { floats, .. }) => r.parse_floats(&e, floats),
+                Some(El::Triangles { indxs }) => r.parse(&e, indxs),
                 Some(El::Mat) => {
                     let Some(El::Node(Node { mat, .. })) = stack.iter_mut().rev().nth(1) else {
                         return Err(Error::MatrixNotFound);
                     };
 
-                    let e = str::from_utf8(&e)?;
-                    for f in e.split_whitespace() {
-                        let f = f.parse().map_err(|_| Error::Parse(f.to_owned()))?;
-                        mat.push(f);
-                    }
-                }
-                Some(El::NameArray { names }) => {
-                    let e = str::from_utf8(&e)?;
-                    for n in e.split_whitespace() {
-                        names.push(Name::from_str(n)?);
-                    }
+                    r.parse_floats(&e, mat);
                 }
+                Some(El::NameArray { names }) => match library {
+                    Library::Animations => r.parse_with(&e, names, |name| {
+                        let name = str::from_utf8(name)?;
+                        Name::from_str(name).map(|_| name.to_owned())
+                    }),
+                    _ => r.parse(&e, names),
+                },
                 _ => {}
             },
             Ok(Event::Eof) => break,
             Err(err) => return Err(Error::XmlError(err)),
             _ => {}
         }
+
+        if closed {
+            r.elements.pop();
+        }
     }
 
     Ok(doc)
 }
 
+/// Problems found while reading the document.
 pub struct Failed {
+    pub problems: Vec<Problem>,
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, problem) in self.problems.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A problem at some place of the document.
+pub struct Problem {
     pub err: Error,
     pub line: usize,
+
+    /// The column in bytes.
+    pub col: usize,
+
+    /// The element path, such as `library_animations/animation[id=walk]/source`.
+    pub path: String,
+
+    /// The part of the line around the problem.
+    pub snippet: String,
+
+    /// The column of the problem in the snippet.
+    pub snippet_col: usize,
 }
 
-impl fmt::Display for Failed {
+impl fmt::Display for Problem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} at line {}", self.err, self.line)
+        let Self {
+            err,
+            line,
+            col,
+            path,
+            ..
+        } = self;
+
+        write!(f, "{err} at line {line}, column {col}")?;
+        if !path.is_empty() {
+            write!(f, " in {path}")?;
+        }
+
+        // There is no snippet if the markup itself is broken
+        if self.snippet.is_empty() {
+            return Ok(());
+        }
+
+        let margin = line.to_string().len();
+        writeln!(f)?;
+        writeln!(f, "{line} | {}", self.snippet)?;
+        write!(f, "{:margin$} | {:>col$}", "", "^", col = self.snippet_col)
     }
 }
 
@@ -380,14 +957,38 @@ impl fmt::Display for Error {
 }
 
 enum El {
-    Geometry { id: String, name: String },
-    Source { id: String },
-    FloatArray { floats: Vec<f32> },
-    Triangles { indxs: Vec<u32> },
+    Geometry {
+        id: String,
+        name: String,
+    },
+    Source {
+        id: String,
+    },
+    FloatArray {
+        floats: Vec<f32>,
+    },
+    Accessor(Accessor),
+    Vertices,
+    Triangles {
+        indxs: Vec<u32>,
+    },
     Node(Node),
     Mat,
-    Animation { id: String, name: String },
-    NameArray { names: Vec<Name> },
+    Animation {
+        id: String,
+        name: String,
+        target: Option<String>,
+    },
+    NameArray {
+        names: Vec<String>,
+    },
+    Sampler,
+    Skin,
+    Joints,
+    Clip {
+        name: String,
+        animations: Vec<String>,
+    },
 }
 
 trait GetAttribute {
@@ -417,3 +1018,121 @@ impl GetAttribute for BytesStart<'_> {
             .ok_or_else(|| Error::AttributeNotFound(attr.to_owned()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::BufReader};
+
+    #[test]
+    fn accessor_params() {
+        let source = Source {
+            id: String::new(),
+            floats: vec![9., 1., 2., 0., 3., 4., 0.],
+            names: vec![],
+            idents: vec![],
+            accessor: Some(Accessor {
+                count: 2,
+                offset: 1,
+                stride: 3,
+                params: vec!["T".to_owned(), "S".to_owned(), String::new()],
+            }),
+        };
+
+        assert_eq!(source.get(0, ["S", "T"]), Some([2., 1.]));
+        assert_eq!(source.get(1, ["S", "T"]), Some([4., 3.]));
+        assert_eq!(source.get(2, ["S", "T"]), None);
+    }
+
+    #[test]
+    fn problems() {
+        let src = "<COLLADA>\r\n<library_animations>\r\n\
+            <animation id=\"a\" name=\"A\"><source id=\"s\">\r\n\
+            <float_array count=\"2\">0 x</float_array></source></animation>\r\n\
+            <animation><source id=\"t\"><float_array count=\"1\">y</float_array></source></animation>\r\n\
+            </library_animations>\r\n</COLLADA>";
+
+        let Err(Failed { problems }) = read(src.as_bytes(), None) else {
+            panic!("expected problems");
+        };
+
+        let places: Vec<_> = problems.iter().map(|p| (p.line, p.col)).collect();
+        assert_eq!(
+            places,
+            [(4, 26), (5, 1), (5, 50)],
+            "all problems are reported"
+        );
+        assert_eq!(
+            problems[0].path,
+            "library_animations/animation[id=a]/source[id=s]/float_array"
+        );
+        assert_eq!(
+            problems[0].snippet, "0 x",
+            "the snippet is cut from the text"
+        );
+        assert_eq!(problems[1].snippet, "<animation>", "the snippet is the tag");
+    }
+
+    #[test]
+    fn optional_name() {
+        let src = "<COLLADA><library_animations><animation id=\"a\"></animation>\
+            </library_animations></COLLADA>";
+
+        let Ok(doc) = read(src.as_bytes(), None) else {
+            panic!("failed to read");
+        };
+
+        assert_eq!(doc.animations[0].name, "a", "the name is taken from the id");
+        assert!(
+            matches!(&doc.warnings[..], [Problem { err: Error::AttributeNotFound(attr), .. }] if attr == "name"),
+            "the missing name is reported",
+        );
+    }
+
+    #[test]
+    fn small_buffers() {
+        let src = "<COLLADA>\r\n<library_animations>\r\n\
+            <animation id=\"a\" name=\"A\"><source id=\"s\">\r\n\
+            <float_array count=\"3\">0 x 1</float_array></source></animation>\r\n\
+            </library_animations>\r\n</COLLADA>";
+
+        let places = |src| {
+            let Err(Failed { problems }) = read(src, None) else {
+                panic!("expected problems");
+            };
+
+            let places: Vec<_> = problems
+                .into_iter()
+                .map(|p| (p.line, p.col, p.path, p.snippet))
+                .collect();
+
+            places
+        };
+
+        let expected = places(Box::new(src.as_bytes()) as Box<dyn BufRead>);
+        for cap in 1..7 {
+            let src = Box::new(BufReader::with_capacity(cap, src.as_bytes()));
+            assert_eq!(places(src), expected, "problems read with capacity {cap}");
+        }
+    }
+
+    #[test]
+    fn skipped_geometries() {
+        let src = "<COLLADA><library_geometries>\
+            <geometry id=\"a\" name=\"A\"><mesh/></geometry>\
+            <geometry id=\"b\" name=\"B\"><mesh><source id=\"s\">\
+            <float_array count=\"1\">x</float_array></source></mesh></geometry>\
+            </library_geometries></COLLADA>";
+
+        let mut names = vec![];
+        let geometries = Geometries {
+            selects: &|name| name == "A",
+            take: &mut |geom| names.push(geom.name),
+        };
+
+        assert!(
+            read(src.as_bytes(), Some(geometries)).is_ok(),
+            "the broken geometry isn't parsed",
+        );
+        assert_eq!(names, ["A"], "only the selected geometry is read");
+    }
+}
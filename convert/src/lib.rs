@@ -1,16 +1,38 @@
 mod action;
+mod additive;
+mod bake;
+mod bvh;
+mod clip;
+mod container;
 mod format;
 mod mesh;
+mod mirror;
+mod options;
 mod params;
 mod parser;
+mod reduce;
+mod retarget;
+mod root;
 mod skeleton;
 mod target;
+mod timing;
 
 pub use {
-    action::Action,
-    format::{Error as FormatError, Failed},
+    action::{Action, Axis, Indexed, Order, Pose, Rotation, Transform, UnknownAxis},
+    additive::Reference,
+    bake::{bake, Baked, InvalidFrameRate},
+    bvh::{write_bvh, Error as BvhError},
+    clip::{Clip, InvalidClip},
+    container::{open, unpack, Error as OpenError},
+    format::{Error as FormatError, Failed, Problem},
     mesh::{IndexOverflow, Mesh},
-    parser::{parse, Element, Error, Value},
-    skeleton::{Skeleton, ToManyBones},
+    mirror::{mirror, mirror_skeleton},
+    options::{Options, Policy, UnknownPolicy},
+    parser::{parse, parse_bvh, parse_reader, Element, Error, Output, Value, Warning},
+    reduce::{reduce, Tolerance},
+    retarget::retarget,
+    root::{Axes, InvalidAxes, RootMotion},
+    skeleton::{Bone, Error as SkeletonError, Skeleton},
     target::{Target, Unknown},
+    timing::{make_loop, reverse, scale_speed},
 };
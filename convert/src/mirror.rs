@@ -0,0 +1,155 @@
+use crate::{
+    action::{Action, Axis, Component, Interpolation, Keyframe, Value},
+    skeleton::{Bone, Error, Skeleton},
+};
+
+/// Suffixes of left and right bone pairs.
+const SIDES: [(&str, &str); 4] = [(".L", ".R"), ("_L", "_R"), (".l", ".r"), ("_l", "_r")];
+
+/// Mirrors the action across the plane with the given normal.
+///
+/// Left and right bones are swapped. Values are reflected in the bone space,
+/// so for symmetric rigs the normal is usually the X axis, as Blender flips poses.
+pub fn mirror(action: &Action, normal: Axis) -> Action {
+    let mut out = Action::default();
+    if action.is_additive() {
+        out.make_additive();
+    }
+
+    for (bone, keys) in action.keyframes() {
+        let keys = keys
+            .iter()
+            .map(|key| Keyframe {
+                input: key.input,
+                val: reflect(key.val, normal),
+            })
+            .collect();
+
+        out.insert_keys(mirror_name(bone).unwrap_or_else(|| bone.clone()), keys);
+    }
+
+    out
+}
+
+/// Mirrors rest poses of the skeleton across the plane with the given normal.
+///
+/// Left and right bones are swapped, the bone order stays the same.
+///
+/// # Errors
+/// See [`Error`] type for details.
+pub fn mirror_skeleton(sk: &Skeleton, normal: Axis) -> Result<Skeleton, Error> {
+    let f = flags(normal);
+
+    // Conjugation of a matrix by the reflection
+    let reflect_mat = |mut mat: [f32; 16]| {
+        for (idx, v) in mat.iter_mut().enumerate() {
+            let (col, row) = (idx / 4, idx % 4);
+            let flip = |i: usize| f.get(i).copied().unwrap_or_default();
+            *v = neg(*v, flip(col) != flip(row));
+        }
+
+        mat
+    };
+
+    let bones = sk.bones().iter().map(|bone| {
+        let [x, y, z] = bone.pos;
+        let [qx, qy, qz, qw] = bone.rot;
+        Bone {
+            name: mirror_name(&bone.name).unwrap_or_else(|| bone.name.clone()),
+            pos: [neg(x, f[0]), neg(y, f[1]), neg(z, f[2])],
+            rot: [neg(qx, !f[0]), neg(qy, !f[1]), neg(qz, !f[2]), qw],
+            scale: bone.scale,
+            parent: bone.parent,
+            bind: bone.bind.map(reflect_mat),
+            inv_bind: bone.inv_bind.map(reflect_mat),
+        }
+    });
+
+    Skeleton::from_bones(bones.collect())
+}
+
+/// Returns the name of the opposite side bone.
+fn mirror_name(name: &str) -> Option<String> {
+    SIDES.iter().find_map(|&(left, right)| {
+        if let Some(base) = name.strip_suffix(left) {
+            Some(format!("{base}{right}"))
+        } else {
+            let base = name.strip_suffix(right)?;
+            Some(format!("{base}{left}"))
+        }
+    })
+}
+
+/// Returns which axes the reflection flips.
+fn flags(normal: Axis) -> [bool; 3] {
+    match normal {
+        Axis::X => [true, false, false],
+        Axis::Y => [false, true, false],
+        Axis::Z => [false, false, true],
+    }
+}
+
+/// Negates the value if needed, without producing negative zeros.
+fn neg(v: f32, flip: bool) -> f32 {
+    if flip {
+        0. - v
+    } else {
+        v
+    }
+}
+
+fn reflect(mut val: Value, normal: Axis) -> Value {
+    let [x, y, z] = flags(normal);
+
+    // Rotations around the normal are kept and others are reversed,
+    // while locations along the normal are reversed
+    let comps = [
+        (!x, &mut val.rx),
+        (!y, &mut val.ry),
+        (!z, &mut val.rz),
+        (!x, &mut val.qx),
+        (!y, &mut val.qy),
+        (!z, &mut val.qz),
+        (x, &mut val.lx),
+        (y, &mut val.ly),
+        (z, &mut val.lz),
+    ];
+
+    for (flip, comp) in comps {
+        if flip {
+            *comp = comp.map(negate);
+        }
+    }
+
+    val
+}
+
+fn negate(Component { output, int }: Component) -> Component {
+    let int = match int {
+        Interpolation::Bezier([lx, ly, rx, ry]) => {
+            Interpolation::Bezier([lx, neg(ly, true), rx, neg(ry, true)])
+        }
+        Interpolation::Hermite([ix, iy, ox, oy]) => {
+            Interpolation::Hermite([ix, neg(iy, true), ox, neg(oy, true)])
+        }
+        int => int,
+    };
+
+    Component {
+        output: neg(output, true),
+        int,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(mirror_name("hand.L").as_deref(), Some("hand.R"));
+        assert_eq!(mirror_name("hand_R").as_deref(), Some("hand_L"));
+        assert_eq!(mirror_name("foot_l").as_deref(), Some("foot_r"));
+        assert_eq!(mirror_name("spine"), None);
+    }
+}
@@ -0,0 +1,158 @@
+use {
+    crate::{
+        action::{Axis, Order},
+        additive::Reference,
+        clip::Clip,
+        reduce::Tolerance,
+        root::RootMotion,
+        skeleton::Skeleton,
+    },
+    std::{collections::HashMap, error, fmt, str},
+};
+
+/// Conversion options.
+#[derive(Default)]
+pub struct Options {
+    /// Write world-space bind matrices and their inverses of skeleton bones.
+    pub bind: bool,
+
+    /// What to do with bones that can't be placed in a skeleton.
+    pub policy: Policy,
+
+    /// Glob patterns of node names to convert, all nodes are converted if it's empty.
+    pub select: Vec<String>,
+
+    /// Write all action rotations as quaternions.
+    pub quaternion: bool,
+
+    /// Euler rotation orders of bones, XYZ by default.
+    pub orders: HashMap<String, Order>,
+
+    /// Bake actions at the given frame rate.
+    pub bake: Option<f32>,
+
+    /// Remove action keys that can be reconstructed within the tolerance.
+    pub reduce: Option<Tolerance>,
+
+    /// Frame ranges to cut from actions as separate actions.
+    pub clips: Vec<Clip>,
+
+    /// Frame rate of clip ranges and looped actions,
+    /// 24 or the frame rate of a `.bvh` file by default.
+    pub frame_rate: Option<f32>,
+
+    /// Play actions backwards.
+    pub reverse: bool,
+
+    /// Make actions loop seamlessly.
+    pub looping: bool,
+
+    /// Change the playback speed of actions.
+    pub speed: Option<f32>,
+
+    /// Extract root motion of actions to separate tracks.
+    pub root_motion: Option<RootMotion>,
+
+    /// Also write actions and skeletons mirrored across the plane with the normal.
+    pub mirror: Option<Axis>,
+
+    /// Make actions additive to the reference pose.
+    pub additive: Option<Reference>,
+
+    /// Skeleton to check bones of actions against.
+    pub skeleton: Option<Skeleton>,
+}
+
+impl Options {
+    pub(crate) fn selects(&self, name: &str) -> bool {
+        self.select.is_empty()
+            || self
+                .select
+                .iter()
+                .any(|pattern| glob(pattern.as_bytes(), name.as_bytes()))
+    }
+}
+
+/// Matches the name with a glob pattern, where `*` matches
+/// any sequence of characters and `?` matches any single character.
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// The policy for degenerate bones and bones whose parent was dropped.
+#[derive(Clone, Copy, Default)]
+pub enum Policy {
+    /// Skip the bone with all its children.
+    #[default]
+    Skip,
+
+    /// Attach children to the nearest valid ancestor.
+    Reparent,
+
+    /// Stop with an error.
+    Fail,
+}
+
+impl str::FromStr for Policy {
+    type Err = UnknownPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "reparent" => Ok(Self::Reparent),
+            "fail" => Ok(Self::Fail),
+            _ => Err(UnknownPolicy),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownPolicy;
+
+impl fmt::Display for UnknownPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown policy")
+    }
+}
+
+impl error::Error for UnknownPolicy {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match() {
+        assert!(glob(b"*", b""), "star matches empty");
+        assert!(glob(b"Armature*", b"Armature.001"), "prefix");
+        assert!(glob(b"*.L", b"hand.L"), "suffix");
+        assert!(glob(b"h?nd*L", b"hand.L"), "question and star");
+        assert!(glob(b"*a*a*", b"banana"), "backtracking");
+        assert!(!glob(b"Armature", b"Armature.001"), "exact");
+        assert!(!glob(b"*.R", b"hand.L"), "wrong suffix");
+        assert!(!glob(b"?", b""), "question needs a char");
+    }
+}
@@ -8,19 +8,18 @@ pub(crate) struct Parameters {
     pub pos_fn: fn([f32; 3]) -> [f32; 3],
     pub map_fn: fn([f32; 2]) -> [f32; 2],
     pub rot_fn: fn([f32; 4]) -> [f32; 4],
+    pub scl_fn: fn([f32; 3]) -> [f32; 3],
     pub act_fn: fn([f32; 2]) -> [f32; 2],
     pub bez_fn: fn([f32; 4]) -> [f32; 4],
+    pub mat_fn: fn([f32; 16]) -> [f32; 16],
 }
 
 impl Parameters {
-    /// Initialize global parameters.
-    ///
-    /// # Panics
-    /// Panics if global parameters is already set.
-    pub fn init(self) {
-        let val = Box::leak(self.into());
-        let set = PARAMS.with(|params| params.set(val));
-        assert!(set.is_ok(), "parameters is already set");
+    /// Initialize global parameters if they aren't set yet.
+    pub fn init(make: fn() -> Self) {
+        PARAMS.with(|params| {
+            params.get_or_init(|| Box::leak(make().into()));
+        });
     }
 
     /// Get global parameters.
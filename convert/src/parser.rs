@@ -1,15 +1,38 @@
 use {
     crate::{
-        action::{Action, Channel, Interpolation, Rotation},
-        format::{read, Document, Failed, Name, Node},
+        action::{Action, Component, Interpolation, Kind, Make, CHANNELS},
+        additive::{self, Reference},
+        bake::{self, Baked, InvalidFrameRate},
+        bvh::{self, Bvh, Error as BvhError},
+        clip,
+        format::{
+            self, read, Document, Failed, Geometries, Geometry, Name, Node, Problem, Skin, Source,
+        },
         mesh::{IndexOverflow, Mesh, Vert},
+        mirror,
+        options::{Options, Policy},
         params::Parameters,
-        skeleton::{Bone, Skeleton, ToManyBones},
+        reduce, root,
+        skeleton::{Bone, Error as SkeletonError, Skeleton},
         target::Target,
+        timing,
+    },
+    glam::{Mat4, Vec2},
+    std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        fmt,
+        io::BufRead,
     },
-    std::fmt,
 };
 
+pub struct Output {
+    pub elements: Vec<Element>,
+    pub warnings: Vec<Warning>,
+
+    /// Numbers of keys removed by reduction for bones of each action.
+    pub reduced: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
 pub struct Element {
     pub name: String,
     pub val: Value,
@@ -19,28 +42,161 @@ pub enum Value {
     Mesh(Mesh),
     Skeleton(Skeleton),
     Action(Action),
+    Baked(Baked),
+    ObjectAction(Action),
+    ObjectBaked(Baked),
+}
+
+impl Value {
+    /// Returns the kind of the value, object actions are apart from bone ones.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Mesh(_) => "mesh",
+            Self::Skeleton(_) => "skeleton",
+            Self::Action(_) | Self::Baked(_) => "action",
+            Self::ObjectAction(_) | Self::ObjectBaked(_) => "object",
+        }
+    }
 }
 
 /// Parse a collada document.
 ///
 /// # Errors
 /// See [`Error`] type for details.
-pub fn parse(src: &str, target: Target) -> Result<Vec<Element>, Error> {
+pub fn parse(src: &str, target: Target, opts: &Options) -> Result<Output, Error> {
+    parse_reader(src.as_bytes(), target, opts)
+}
+
+/// Parse a collada document as it streams from the reader,
+/// such as a buffered file or a memory-mapped one.
+///
+/// Geometries are converted to meshes as soon as they are read,
+/// and skipped entirely unless meshes are the target and they are selected.
+///
+/// # Errors
+/// See [`Error`] type for details.
+pub fn parse_reader<R>(src: R, target: Target, opts: &Options) -> Result<Output, Error>
+where
+    R: BufRead,
+{
     init_params();
 
     let mut output = vec![];
-    let doc = read(src)?;
+    let mut warnings = vec![];
+    let mut reduced = BTreeMap::new();
+    let mut meshes = Ok(());
+    let mut parse_geometry = |geom: Geometry| {
+        if meshes.is_ok() {
+            meshes = parse_mesh(&geom, &mut output);
+        }
+    };
+
+    let selects = |name: &str| opts.selects(name);
+    let geometry = match target {
+        Target::Mesh | Target::All => Some(Geometries {
+            selects: &selects,
+            take: &mut parse_geometry,
+        }),
+        Target::Skeleton | Target::Action => None,
+    };
+
+    let mut doc = read(src, geometry)?;
+    warnings.extend(doc.warnings.drain(..).map(Warning::OptionalAttribute));
+    meshes?;
 
     match target {
-        Target::Mesh => parse_meshes(doc, &mut output)?,
-        Target::Skeleton => parse_skeletons(doc, &mut output)?,
-        Target::Action => parse_actions(doc, &mut output)?,
+        Target::Mesh => {}
+        Target::Skeleton => parse_skeletons(&doc, opts, &mut output, &mut warnings)?,
+        Target::Action => parse_actions(&doc, opts, &mut output, &mut reduced)?,
+        Target::All => {
+            parse_skeletons(&doc, opts, &mut output, &mut warnings)?;
+            parse_actions(&doc, opts, &mut output, &mut reduced)?;
+        }
+    }
+
+    if let Some(sk) = &opts.skeleton {
+        check_bones(&output, sk, &mut warnings);
+    }
+
+    Ok(Output {
+        elements: output,
+        warnings,
+        reduced,
+    })
+}
+
+/// Parse a `.bvh` motion capture file.
+///
+/// The skeleton and the action are named with the given name. Actions are
+/// processed with the same options as collada ones, and the frame rate of
+/// the file is used by default. There are no meshes in `.bvh` files.
+///
+/// # Errors
+/// See [`Error`] type for details.
+pub fn parse_bvh(src: &str, name: &str, target: Target, opts: &Options) -> Result<Output, Error> {
+    let Bvh {
+        skeleton,
+        action,
+        fps,
+    } = bvh::read(src)?;
+
+    let mut output = vec![];
+    let mut reduced = BTreeMap::new();
+    let root = skeleton.bones().first().map(|bone| bone.name.clone());
+    if let Target::Skeleton | Target::All = target {
+        push_skeleton(name.to_owned(), skeleton, opts, &mut output)?;
+    }
+
+    if let Target::Action | Target::All = target {
+        println!("read {name} .. ");
+        let actions = BTreeMap::from([(name.to_owned(), action)]);
+        let fps = opts.frame_rate.unwrap_or(fps);
+        process_actions(
+            actions,
+            BTreeMap::new(),
+            root.as_deref(),
+            fps,
+            opts,
+            &mut output,
+            &mut reduced,
+        )?;
     }
 
-    Ok(output)
+    let mut warnings = vec![];
+    if let Some(sk) = &opts.skeleton {
+        check_bones(&output, sk, &mut warnings);
+    }
+
+    Ok(Output {
+        elements: output,
+        warnings,
+        reduced,
+    })
+}
+
+/// Reports bones of actions missing from the skeleton,
+/// since indexed actions skip them. Object actions have no bones.
+fn check_bones(elements: &[Element], sk: &Skeleton, warnings: &mut Vec<Warning>) {
+    let mut missing = BTreeSet::new();
+    for Element { val, .. } in elements {
+        let bones: Vec<_> = match val {
+            Value::Action(act) => act.keyframes().keys().collect(),
+            Value::Baked(baked) => baked.bones().keys().collect(),
+            Value::Mesh(_)
+            | Value::Skeleton(_)
+            | Value::ObjectAction(_)
+            | Value::ObjectBaked(_) => continue,
+        };
+
+        for bone in bones {
+            if sk.resolve(bone).is_none() && missing.insert(bone) {
+                warnings.push(Warning::UnknownBone { bone: bone.clone() });
+            }
+        }
+    }
 }
 
-fn init_params() {
+pub(crate) fn init_params() {
     fn update<const D: u32>(v: f32) -> f32 {
         let a = u32::pow(10, D) as f32;
         let mut v = (v * a).round() / a;
@@ -51,158 +207,343 @@ fn init_params() {
         v
     }
 
-    Parameters {
+    Parameters::init(|| Parameters {
         pos_fn: |vs| vs.map(update::<4>),
         map_fn: |[u, v]| [u, 1. - v].map(update::<8>),
         rot_fn: |vs| vs.map(update::<4>),
+        scl_fn: |vs| vs.map(update::<4>),
         act_fn: |vs| vs.map(update::<4>),
         bez_fn: |vs| vs.map(update::<4>),
-    }
-    .init();
+        mat_fn: |vs| vs.map(update::<4>),
+    });
 }
 
-fn parse_meshes(doc: Document, output: &mut Vec<Element>) -> Result<(), Error> {
+fn parse_mesh(geom: &Geometry, output: &mut Vec<Element>) -> Result<(), Error> {
     let params = Parameters::get();
-    for geom in doc.geometry {
-        println!("read {} ({}) .. ", geom.name, geom.id);
-
-        let mut verts = vec![];
-        let mut positions_floats = vec![];
-        let mut map_floats = vec![];
-        for source in geom.sources {
-            if source.id.ends_with("-positions") {
-                positions_floats = source.floats;
-            } else if source.id.ends_with("-map-0") {
-                map_floats = source.floats;
-            }
-        }
+    println!("read {} ({}) .. ", geom.name, geom.id);
 
-        let mut max_offset = 1;
-        let mut vertices_input = None;
-        let mut map_input = None;
-        for input in geom.triangles.inputs {
-            if input.source.ends_with("-vertices") {
-                vertices_input = Some(input.offset);
-            } else if input.source.ends_with("-map-0") {
-                map_input = Some(input.offset);
-            }
+    // Positions are referenced through the `<vertices>` element,
+    // texture coordinates either by triangles or by vertices
+    let (triangles, vertices) = (&geom.triangles, &geom.vertices);
+    let vertex = format::find_input(&triangles.inputs, "VERTEX").ok_or(Error::NoVertices)?;
+    let positions =
+        format::find_source(vertices, &geom.sources, "POSITION").ok_or(Error::NoVertices)?;
 
-            let offset = input.offset + 1;
-            if offset > max_offset {
-                max_offset = offset;
-            }
-        }
-
-        let Some(vertices_input) = vertices_input else {
-            return Err(Error::NoVertices);
-        };
+    let (map_offset, map) = match format::find_input(&triangles.inputs, "TEXCOORD") {
+        Some(input) => (
+            input.offset,
+            format::find_source(&triangles.inputs, &geom.sources, "TEXCOORD"),
+        ),
+        None => (
+            vertex.offset,
+            format::find_source(vertices, &geom.sources, "TEXCOORD"),
+        ),
+    };
 
-        let Some(map_input) = map_input else {
-            return Err(Error::NoTextureMap);
-        };
-
-        for index_chunk in geom.triangles.indxs.chunks(max_offset) {
-            let pos = *index_chunk.get(vertices_input).ok_or(Error::Index)? as usize;
-            let map = *index_chunk.get(map_input).ok_or(Error::Index)? as usize;
-
-            let pos_stride = pos * 3;
-            let x = *positions_floats.get(pos_stride).ok_or(Error::Index)?;
-            let y = *positions_floats.get(pos_stride + 1).ok_or(Error::Index)?;
-            let z = *positions_floats.get(pos_stride + 2).ok_or(Error::Index)?;
-            let map_stride = map * 2;
-            let u = *map_floats.get(map_stride).ok_or(Error::Index)?;
-            let v = *map_floats.get(map_stride + 1).ok_or(Error::Index)?;
-
-            verts.push(Vert {
-                pos: (params.pos_fn)([x, y, z]),
-                map: (params.map_fn)([u, v]),
-            });
-        }
+    let map = map.ok_or(Error::NoTextureMap)?;
+    let stride = triangles
+        .inputs
+        .iter()
+        .map(|input| input.offset + 1)
+        .max()
+        .unwrap_or(1);
 
-        let verts: Vec<_> = verts
-            .chunks_exact(3)
-            .map(|tri| match tri {
-                &[a, b, c] => [a, b, c],
-                _ => unreachable!(),
-            })
-            .collect();
+    let mut verts = vec![];
+    for index_chunk in triangles.indxs.chunks(stride) {
+        let pos = *index_chunk.get(vertex.offset).ok_or(Error::Index)? as usize;
+        let uv = *index_chunk.get(map_offset).ok_or(Error::Index)? as usize;
+        let [x, y, z] = positions.get(pos, ["X", "Y", "Z"]).ok_or(Error::Index)?;
+        let [u, v] = map.get(uv, ["S", "T"]).ok_or(Error::Index)?;
 
-        let mesh = Mesh::from_verts(&verts)?;
-        output.push(Element {
-            name: geom.name,
-            val: Value::Mesh(mesh),
+        verts.push(Vert {
+            pos: (params.pos_fn)([x, y, z]),
+            map: (params.map_fn)([u, v]),
         });
     }
 
+    let verts: Vec<_> = verts
+        .chunks_exact(3)
+        .map(|tri| match tri {
+            &[a, b, c] => [a, b, c],
+            _ => unreachable!(),
+        })
+        .collect();
+
+    let mesh = Mesh::from_verts(&verts)?;
+    output.push(Element {
+        name: geom.name.clone(),
+        val: Value::Mesh(mesh),
+    });
+
     Ok(())
 }
 
-fn parse_skeletons(doc: Document, output: &mut Vec<Element>) -> Result<(), Error> {
-    fn visit_node(node: Node, parent: Option<&str>, sk: &mut Skeleton) -> Result<(), Error> {
-        use glam::Mat4;
+fn parse_skeletons(
+    doc: &Document,
+    opts: &Options,
+    output: &mut Vec<Element>,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), Error> {
+    struct Visitor<'a> {
+        sk: Skeleton,
+        binds: Option<&'a HashMap<&'a str, Mat4>>,
+        policy: Policy,
+        warnings: &'a mut Vec<Warning>,
+    }
 
-        match node.ty.as_str() {
-            "NODE" => {}
-            "JOINT" => {
-                let (_, rot, pos) = {
-                    let array = node.mat.try_into().map_err(|_| Error::MatSize)?;
-                    let mat = Mat4::from_cols_array(&array).transpose();
-                    if mat.determinant() == 0. {
-                        let name = node.name;
-                        eprintln!("failed to parse the bone {name} since it's determinant is zero");
-                        return Ok(());
-                    }
+    /// The nearest bone above the visited node.
+    #[derive(Clone)]
+    struct Parent {
+        bone: Option<u16>,
+        /// The transform between the parent bone and the visited node
+        /// when there are dropped nodes in between.
+        offset: Mat4,
+        /// The nearest dropped node in between.
+        lost: Option<String>,
+    }
 
-                    mat.to_scale_rotation_translation()
-                };
+    impl Parent {
+        const ROOT: Self = Self {
+            bone: None,
+            offset: Mat4::IDENTITY,
+            lost: None,
+        };
 
-                let params = Parameters::get();
-                sk.push(Bone {
-                    name: node.name.clone(),
-                    pos: (params.pos_fn)(pos.into()),
-                    rot: (params.rot_fn)(rot.into()),
-                    parent: parent.and_then(|name| sk.get(name)),
-                })?;
+        /// Passes through an intermediate node, so its transform
+        /// is kept in the offset of the next bone.
+        fn through(&self, mat: Mat4) -> Self {
+            Self {
+                bone: self.bone,
+                offset: self.offset * mat,
+                lost: self.lost.clone(),
             }
-            _ => return Err(Error::UndefinedNode(node.ty)),
         }
 
-        // Then children come, so parent's index
-        // will be less than self index.
-        for child in node.children {
-            visit_node(child, Some(&node.name), sk)?;
+        fn lose(&self, name: &str, mat: Mat4) -> Self {
+            Self {
+                bone: self.bone,
+                offset: self.offset * mat,
+                lost: Some(name.to_owned()),
+            }
         }
+    }
 
-        Ok(())
+    impl Visitor<'_> {
+        fn visit_node(
+            &mut self,
+            node: &Node,
+            parent: Parent,
+            parent_world: Mat4,
+        ) -> Result<(), Error> {
+            let mat = <&[f32; 16]>::try_from(node.mat.as_slice())
+                .map(|array| Mat4::from_cols_array(array).transpose());
+
+            let world;
+            let next;
+            match node.ty.as_str() {
+                "NODE" => {
+                    let mat = mat.unwrap_or(Mat4::IDENTITY);
+
+                    // Nodes above the skeleton, such as the armature object, aren't
+                    // in the skeleton space, as skin binds and bone transforms are
+                    (world, next) = match parent.bone {
+                        Some(_) => (parent_world * mat, parent.through(mat)),
+                        None => (parent_world, Parent::ROOT),
+                    };
+                }
+                "JOINT" => {
+                    let mat = mat.map_err(|_| Error::MatSize)?;
+                    world = parent_world * mat;
+                    if mat.determinant() == 0. {
+                        self.warnings.push(Warning::DegenerateBone {
+                            bone: node.name.clone(),
+                            policy: self.policy,
+                        });
+
+                        match self.policy {
+                            Policy::Skip => return Ok(()),
+                            Policy::Reparent => {
+                                // The rotation and scale can't be recovered from
+                                // the degenerate matrix, so keep only the translation
+                                let pos = Mat4::from_translation(mat.w_axis.truncate());
+                                let world = parent_world * pos;
+                                for child in &node.children {
+                                    self.visit_node(child, parent.lose(&node.name, pos), world)?;
+                                }
+
+                                return Ok(());
+                            }
+                            Policy::Fail => return Err(Error::DegenerateBone(node.name.clone())),
+                        }
+                    }
+
+                    if let Some(lost) = &parent.lost {
+                        self.warnings.push(Warning::OrphanedBone {
+                            bone: node.name.clone(),
+                            lost: lost.clone(),
+                            policy: self.policy,
+                        });
+
+                        match self.policy {
+                            Policy::Skip => return Ok(()),
+                            Policy::Reparent => {}
+                            Policy::Fail => return Err(Error::OrphanedBone(node.name.clone())),
+                        }
+                    }
+
+                    let (scale, rot, pos) = (parent.offset * mat).to_scale_rotation_translation();
+                    let params = Parameters::get();
+                    let scale = (params.scl_fn)(scale.into());
+                    let [x, y, z] = scale;
+                    if x != y || x != z {
+                        self.warnings.push(Warning::NonUniformScale {
+                            bone: node.name.clone(),
+                            scale,
+                        });
+                    }
+
+                    let bind = self.binds.map(|binds| {
+                        let inv = [node.sid.as_deref(), Some(&node.name)]
+                            .into_iter()
+                            .flatten()
+                            .find_map(|name| binds.get(name).copied());
+
+                        match inv {
+                            Some(inv) => (inv.inverse(), inv),
+                            None => (world, world.inverse()),
+                        }
+                    });
+
+                    let idx = self.sk.push(Bone {
+                        name: node.name.clone(),
+                        pos: (params.pos_fn)(pos.into()),
+                        rot: (params.rot_fn)(rot.into()),
+                        scale: (scale != [1.; 3]).then_some(scale),
+                        parent: parent.bone,
+                        bind: bind.map(|(bind, _)| (params.mat_fn)(bind.to_cols_array())),
+                        inv_bind: bind.map(|(_, inv)| (params.mat_fn)(inv.to_cols_array())),
+                    })?;
+
+                    next = Parent {
+                        bone: Some(idx),
+                        ..Parent::ROOT
+                    };
+                }
+                _ => {
+                    // Other node types can't contain bones
+                    self.warnings.push(Warning::UndefinedNode {
+                        node: node.name.clone(),
+                        ty: node.ty.clone(),
+                    });
+
+                    return Ok(());
+                }
+            }
+
+            // Then children come, so parent's index
+            // will be less than self index.
+            for child in &node.children {
+                self.visit_node(child, next.clone(), world)?;
+            }
+
+            Ok(())
+        }
     }
 
-    for node in doc.nodes {
+    let binds = if opts.bind {
+        read_inverse_binds(&doc.skins)?
+    } else {
+        HashMap::default()
+    };
+
+    for node in &doc.nodes {
+        if !opts.selects(&node.name) {
+            continue;
+        }
+
         println!("read {} ({}) .. ", node.name, node.id);
 
         let name = node.name.clone();
-        let mut sk = Skeleton::default();
+        let mut visitor = Visitor {
+            sk: Skeleton::default(),
+            binds: opts.bind.then_some(&binds),
+            policy: opts.policy,
+            warnings,
+        };
 
         // The root node comes first,
         // so it's index will be 0
-        visit_node(node, None, &mut sk)?;
+        visitor.visit_node(node, Parent::ROOT, Mat4::IDENTITY)?;
 
+        let sk = visitor.sk;
         if sk.is_empty() {
             println!("skipped {name}");
             continue;
         }
 
+        push_skeleton(name, sk, opts, output)?;
+    }
+
+    Ok(())
+}
+
+/// Pushes the skeleton to the output, along with the mirrored one if needed.
+fn push_skeleton(
+    name: String,
+    sk: Skeleton,
+    opts: &Options,
+    output: &mut Vec<Element>,
+) -> Result<(), Error> {
+    if let Some(normal) = opts.mirror {
         output.push(Element {
-            name,
-            val: Value::Skeleton(sk),
+            name: format!("{name}_mirrored"),
+            val: Value::Skeleton(mirror::mirror_skeleton(&sk, normal)?),
         });
     }
 
+    output.push(Element {
+        name,
+        val: Value::Skeleton(sk),
+    });
+
     Ok(())
 }
 
-fn parse_actions(doc: Document, output: &mut Vec<Element>) -> Result<(), Error> {
-    use std::iter;
+fn read_inverse_binds(skins: &[Skin]) -> Result<HashMap<&str, Mat4>, Error> {
+    let mut binds = HashMap::new();
+    for skin in skins {
+        let source = |semantic| {
+            let input = skin
+                .joints
+                .iter()
+                .find(|input| input.semantic == semantic)?;
+            let id = input.source.strip_prefix('#').unwrap_or(&input.source);
+            skin.sources.iter().find(|source| source.id == id)
+        };
+
+        let (Some(joints), Some(mats)) = (source("JOINT"), source("INV_BIND_MATRIX")) else {
+            continue;
+        };
+
+        if joints.idents.len() * 16 != mats.floats.len() {
+            return Err(Error::ArrayLen);
+        }
+
+        for (name, mat) in joints.idents.iter().zip(mats.floats.chunks_exact(16)) {
+            let mat = Mat4::from_cols_slice(mat).transpose();
+            binds.entry(name.as_str()).or_insert(mat);
+        }
+    }
+
+    Ok(binds)
+}
 
+fn parse_actions(
+    doc: &Document,
+    opts: &Options,
+    output: &mut Vec<Element>,
+    reduced: &mut BTreeMap<String, BTreeMap<String, usize>>,
+) -> Result<(), Error> {
     fn to_rads(deg: f32) -> f32 {
         use std::f32::consts::PI;
 
@@ -211,85 +552,348 @@ fn parse_actions(doc: Document, output: &mut Vec<Element>) -> Result<(), Error>
         deg * M
     }
 
+    // Animation ids referenced by clips
+    let mut clips = HashMap::new();
+    for clip in &doc.clips {
+        for id in &clip.animations {
+            clips.insert(id.as_str(), clip.name.as_str());
+        }
+    }
+
     let params = Parameters::get();
-    let mut action = Action::default();
-    for anim in doc.animations {
-        if anim.sources.is_empty() {
+    let mut actions: BTreeMap<String, Action> = BTreeMap::new();
+    let mut objects: BTreeMap<String, Action> = BTreeMap::new();
+    for anim in &doc.animations {
+        if anim.sources.is_empty() || !opts.selects(&anim.name) {
             continue;
         }
 
         println!("read {} ({}) .. ", anim.name, anim.id);
 
-        let (chan, bone) = {
+        // Bone animation ids are written as `{object}_{action}___{bone}___{channel}`
+        // and object ones as `{object}_{action}_{channel}`
+        let (chan, angular, node, object, prefix) = if anim.id.contains("___") {
             let mut parts = anim.id.rsplit("___");
-            let chan = match parts.next().ok_or(Error::AnimationId)? {
-                "rotation_euler_X" => Channel::RotationX,
-                "rotation_euler_Y" => Channel::RotationY,
-                "rotation_euler_Z" => Channel::RotationZ,
-                _ => return Err(Error::AnimationId),
-            };
+            let (chan, angular) =
+                channel(parts.next().ok_or(Error::AnimationId)?).ok_or(Error::AnimationId)?;
 
             let bone = parts.next().ok_or(Error::AnimationId)?;
-            (chan, bone)
+            let prefix = parts.next();
+            (chan, angular, bone, false, prefix)
+        } else {
+            let (prefix, (chan, angular)) = CHANNELS
+                .iter()
+                .find_map(|&(name, _, make, kind)| {
+                    let prefix = anim.id.strip_suffix(name)?.strip_suffix('_')?;
+                    Some((prefix, (make, kind == Kind::Angle)))
+                })
+                .ok_or(Error::AnimationId)?;
+
+            // The channel target starts with the id of the animated node
+            let object = anim
+                .target
+                .as_deref()
+                .and_then(|target| target.split('/').next())
+                .and_then(|id| find_node(&doc.nodes, id))
+                .map_or(anim.name.as_str(), |node| node.name.as_str());
+
+            (chan, angular, object, true, Some(prefix))
         };
 
-        let mut inputs = vec![];
-        let mut outputs = vec![];
-        let mut names = vec![];
-        let mut intangent = vec![];
-        let mut outtangent = vec![];
-        for source in anim.sources {
-            if source.id.ends_with("-input") {
-                inputs = source.floats;
-            } else if source.id.ends_with("-output") {
-                outputs = source.floats;
-            } else if source.id.ends_with("-interpolation") {
-                names = source.names;
-            } else if source.id.ends_with("-intangent") {
-                intangent = source.floats;
-            } else if source.id.ends_with("-outtangent") {
-                outtangent = source.floats;
-            }
-        }
+        // The action name is taken from a clip or from the id prefix,
+        // which Blender writes as the object name followed by the action name
+        let action_name = [Some(&anim.id), anim.parent.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|id| clips.get(id.as_str()).copied())
+            .or_else(|| {
+                let prefix = prefix?;
+                let name = prefix
+                    .strip_prefix(anim.name.as_str())
+                    .and_then(|name| name.strip_prefix('_'))
+                    .unwrap_or(prefix);
+
+                (!name.is_empty()).then_some(name)
+            })
+            .unwrap_or("action");
+
+        let actions = if object { &mut objects } else { &mut actions };
+        let action = actions.entry(action_name.to_owned()).or_default();
 
-        if inputs.len() != outputs.len() || inputs.len() != names.len() {
+        // Rotations are written in degrees
+        let to_units = |v: f32| if angular { to_rads(v) } else { v };
+
+        // Sources are referenced by semantics of sampler inputs
+        let source = |semantic: &str| {
+            format::find_source(&anim.inputs, &anim.sources, semantic)
+                .ok_or_else(|| Error::NoSource(semantic.to_owned()))
+        };
+
+        let input = source("INPUT")?;
+        let output = source("OUTPUT")?;
+        let names = &source("INTERPOLATION")?.names;
+        let intangent = source("IN_TANGENT").ok();
+        let outtangent = source("OUT_TANGENT").ok();
+        let len = input.count();
+        if output.count() != len || names.len() != len {
             return Err(Error::ArrayLen);
         }
 
-        let ns = iter::zip(0.., names);
-        let io = iter::zip(inputs, outputs);
-        for ((idx, name), (input, output)) in iter::zip(ns, io) {
-            let (x, y) = (input, to_rads(output));
-            let [input, output] = (params.act_fn)([x, y]);
-            let int = match name {
-                Name::Linear => Interpolation::Linear,
-                Name::Bezier => {
-                    let stride = idx * 2;
-                    let lx = intangent.get(stride).ok_or(Error::Index)?;
-                    let ly = intangent.get(stride + 1).ok_or(Error::Index)?;
-                    let rx = outtangent.get(stride).ok_or(Error::Index)?;
-                    let ry = outtangent.get(stride + 1).ok_or(Error::Index)?;
-                    Interpolation::Bezier((params.bez_fn)([lx - x, ly - y, rx - x, ry - y]))
+        let points = (0..len)
+            .map(|idx| {
+                let [x] = input.get(idx, ["TIME"])?;
+                let y = output.value(idx)?;
+                Some(Vec2::new(x, to_units(y)))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::Index)?;
+
+        for (idx, name) in names.iter().enumerate() {
+            let point = points[idx];
+            let prev = points[idx.saturating_sub(1)];
+            let next = points[usize::min(idx + 1, points.len() - 1)];
+            let (key, int) = match name {
+                Name::Linear => (point, Interpolation::Linear),
+                Name::Step => (point, Interpolation::Step),
+                Name::Bezier | Name::Hermite => {
+                    let tangent = |source: Option<&Source>| {
+                        let [x, y] = source?.get(idx, ["X", "Y"])?;
+                        Some([x, to_units(y)])
+                    };
+
+                    let [lx, ly] = tangent(intangent).ok_or(Error::Index)?;
+                    let [rx, ry] = tangent(outtangent).ok_or(Error::Index)?;
+                    let int = match name {
+                        // Bezier tangents are control points
+                        Name::Bezier => {
+                            let [x, y] = point.to_array();
+                            Interpolation::Bezier((params.bez_fn)([lx - x, ly - y, rx - x, ry - y]))
+                        }
+                        // Hermite tangents are vectors
+                        _ => Interpolation::Hermite((params.bez_fn)([lx, ly, rx, ry])),
+                    };
+
+                    (point, int)
+                }
+                Name::Cardinal => {
+                    // Catmull-Rom tangent from neighbor keys
+                    let steps = (idx > 0) as u8 + (idx + 1 < points.len()) as u8;
+                    let tangent = (next - prev) / f32::from(steps.max(1));
+                    let [x, y] = (tangent / 3.).to_array();
+                    (
+                        point,
+                        Interpolation::Bezier((params.bez_fn)([-x, -y, x, y])),
+                    )
+                }
+                Name::Bspline => {
+                    // Uniform cubic B-spline segment as bezier,
+                    // the curve doesn't pass through inner control points
+                    let key = (prev + point * 4. + next) / 6.;
+                    let [lx, ly] = ((prev + point * 2.) / 3. - key).to_array();
+                    let [rx, ry] = ((point * 2. + next) / 3. - key).to_array();
+                    (
+                        key,
+                        Interpolation::Bezier((params.bez_fn)([lx, ly, rx, ry])),
+                    )
                 }
             };
 
-            let rot = Rotation { output, int };
-            action.insert_channel(bone.to_owned(), input, chan(rot));
+            // B-spline end points are repeated three times,
+            // so the curve starts and ends exactly at them
+            let end = (point, Interpolation::Bezier([0.; 4]));
+            let bspline = matches!(name, Name::Bspline);
+            let first = (bspline && idx == 0).then_some(end);
+            let last = (bspline && idx + 1 == points.len()).then_some(end);
+            for (key, int) in first.into_iter().chain([(key, int)]).chain(last) {
+                let [input, output] = (params.act_fn)(key.to_array());
+                let comp = Component { output, int };
+                action.insert_channel(node.to_owned(), input, chan(comp));
+            }
         }
     }
 
-    if action.is_empty() {
+    // Euler axes may be keyed at different times
+    for action in actions.values_mut().chain(objects.values_mut()) {
+        action.fill_eulers();
+        action.round();
+    }
+
+    let root = root_bone(&doc.nodes);
+    let fps = opts.frame_rate.unwrap_or(24.);
+    process_actions(actions, objects, root, fps, opts, output, reduced)
+}
+
+/// Applies action options and pushes bone and object actions to the output.
+///
+/// The frame rate is used for clip ranges and looped actions.
+/// Numbers of keys removed by reduction are collected in `reduced`.
+#[allow(clippy::too_many_arguments)]
+fn process_actions(
+    mut actions: BTreeMap<String, Action>,
+    mut objects: BTreeMap<String, Action>,
+    root: Option<&str>,
+    fps: f32,
+    opts: &Options,
+    output: &mut Vec<Element>,
+    reduced: &mut BTreeMap<String, BTreeMap<String, usize>>,
+) -> Result<(), Error> {
+    use std::iter;
+
+    if actions.is_empty() && objects.is_empty() {
         println!("skipped action");
     }
 
-    output.push(Element {
-        name: "action".to_owned(),
-        val: Value::Action(action),
-    });
+    if !fps.is_finite() || fps <= 0. {
+        return Err(Error::FrameRate(fps));
+    }
+
+    if !opts.clips.is_empty() {
+        actions = clip::cut_clips(actions, &opts.clips, fps);
+        objects = clip::cut_clips(objects, &opts.clips, fps);
+    }
+
+    if let Some(speed) = opts.speed {
+        if !speed.is_finite() || speed <= 0. {
+            return Err(Error::Speed(speed));
+        }
+    }
+
+    for action in actions.values_mut().chain(objects.values_mut()) {
+        if opts.reverse {
+            *action = timing::reverse(action);
+        }
+
+        if opts.looping {
+            *action = timing::make_loop(action, 1. / fps);
+        }
+
+        if let Some(speed) = opts.speed {
+            *action = timing::scale_speed(action, speed);
+        }
+    }
+
+    if let Some(motion) = &opts.root_motion {
+        let bone = motion.bone.as_deref().or(root);
+        let mut tracks = BTreeMap::new();
+        for (name, action) in &mut actions {
+            let Some(track) =
+                bone.and_then(|bone| root::extract(action, bone, motion.axes, &opts.orders))
+            else {
+                println!("no root motion in {name}");
+                continue;
+            };
+
+            tracks.insert(format!("{name}_root"), track);
+        }
+
+        actions.extend(tracks);
+    }
+
+    // Actions are made additive before mirroring,
+    // so mirrored ones are relative to the mirrored reference
+    if let Some(reference) = &opts.additive {
+        let (poses, skip) = match reference {
+            Reference::Rest => (BTreeMap::new(), None),
+            Reference::Action(name) => {
+                let poses = actions
+                    .get(name)
+                    .ok_or_else(|| Error::NoReference(name.clone()))?
+                    .pose_at(0.);
+
+                (poses, Some(name))
+            }
+        };
+
+        // The reference itself stays a full pose
+        for (_, action) in actions.iter_mut().filter(|&(name, _)| Some(name) != skip) {
+            additive::subtract(action, &poses, &opts.orders);
+        }
+    }
+
+    if let Some(normal) = opts.mirror {
+        for actions in [&mut actions, &mut objects] {
+            let mirrored: Vec<_> = actions
+                .iter()
+                .map(|(name, action)| (format!("{name}_mirrored"), mirror::mirror(action, normal)))
+                .collect();
+
+            actions.extend(mirrored);
+        }
+    }
+
+    let actions = iter::zip(actions, iter::repeat(false));
+    let objects = iter::zip(objects, iter::repeat(true));
+    for ((name, mut action), object) in actions.chain(objects) {
+        if let Some(fps) = opts.bake {
+            let baked = bake::bake(&action, fps, &opts.orders)
+                .map_err(|InvalidFrameRate(fps)| Error::FrameRate(fps))?;
+            output.push(Element {
+                name,
+                val: if object {
+                    Value::ObjectBaked(baked)
+                } else {
+                    Value::Baked(baked)
+                },
+            });
+
+            continue;
+        }
+
+        if opts.quaternion {
+            action.make_quaternions(&opts.orders);
+            action.round();
+        }
+
+        if let Some(tol) = opts.reduce {
+            let mut removed = reduce::reduce(&mut action, tol);
+            removed.retain(|_, removed| *removed != 0);
+            if !removed.is_empty() {
+                reduced.insert(name.clone(), removed);
+            }
+        }
+
+        output.push(Element {
+            name,
+            val: if object {
+                Value::ObjectAction(action)
+            } else {
+                Value::Action(action)
+            },
+        });
+    }
 
     Ok(())
 }
 
+fn channel(name: &str) -> Option<(Make, bool)> {
+    CHANNELS
+        .iter()
+        .find(|&&(chan, ..)| chan == name)
+        .map(|&(_, _, make, kind)| (make, kind == Kind::Angle))
+}
+
+/// Returns the name of the top bone of the first skeleton,
+/// as it's written in animation ids.
+fn root_bone(nodes: &[Node]) -> Option<&str> {
+    nodes.iter().find_map(|node| {
+        if node.ty == "JOINT" {
+            Some(node.sid.as_deref().unwrap_or(&node.name))
+        } else {
+            root_bone(&node.children)
+        }
+    })
+}
+
+fn find_node<'a>(nodes: &'a [Node], id: &str) -> Option<&'a Node> {
+    nodes.iter().find_map(|node| {
+        if node.id == id {
+            Some(node)
+        } else {
+            find_node(&node.children, id)
+        }
+    })
+}
+
 /// All possible parser errors.
 pub enum Error {
     Document(Failed),
@@ -299,9 +903,15 @@ pub enum Error {
     MatSize,
     ArrayLen,
     AnimationId,
-    UndefinedNode(String),
+    NoSource(String),
+    FrameRate(f32),
+    Speed(f32),
+    NoReference(String),
+    Bvh(BvhError),
+    DegenerateBone(String),
+    OrphanedBone(String),
     IndexOverflow(IndexOverflow),
-    ToManyBones(ToManyBones),
+    Skeleton(SkeletonError),
 }
 
 impl From<Failed> for Error {
@@ -310,15 +920,21 @@ impl From<Failed> for Error {
     }
 }
 
+impl From<BvhError> for Error {
+    fn from(v: BvhError) -> Self {
+        Self::Bvh(v)
+    }
+}
+
 impl From<IndexOverflow> for Error {
     fn from(v: IndexOverflow) -> Self {
         Self::IndexOverflow(v)
     }
 }
 
-impl From<ToManyBones> for Error {
-    fn from(v: ToManyBones) -> Self {
-        Self::ToManyBones(v)
+impl From<SkeletonError> for Error {
+    fn from(v: SkeletonError) -> Self {
+        Self::Skeleton(v)
     }
 }
 
@@ -332,9 +948,723 @@ impl fmt::Display for Error {
             Self::MatSize => write!(f, "wrong matrix size"),
             Self::ArrayLen => write!(f, "wrong array length"),
             Self::AnimationId => write!(f, "invalid animation id"),
-            Self::UndefinedNode(node) => write!(f, "undefined node {node}"),
+            Self::NoSource(semantic) => write!(f, "the {semantic} source not found"),
+            Self::FrameRate(fps) => write!(f, "invalid frame rate {fps}"),
+            Self::Speed(speed) => write!(f, "invalid speed {speed}"),
+            Self::NoReference(name) => write!(f, "the reference action {name} not found"),
+            Self::Bvh(err) => write!(f, "failed to parse bvh: {err}"),
+            Self::DegenerateBone(bone) => write!(f, "the bone {bone} has zero determinant"),
+            Self::OrphanedBone(bone) => write!(f, "the bone {bone} has lost its parent"),
             Self::IndexOverflow(err) => write!(f, "{err}"),
-            Self::ToManyBones(err) => write!(f, "{err}"),
+            Self::Skeleton(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Non-fatal problems found while parsing.
+pub enum Warning {
+    DegenerateBone {
+        bone: String,
+        policy: Policy,
+    },
+    OrphanedBone {
+        bone: String,
+        lost: String,
+        policy: Policy,
+    },
+    NonUniformScale {
+        bone: String,
+        scale: [f32; 3],
+    },
+    UndefinedNode {
+        node: String,
+        ty: String,
+    },
+    UnknownBone {
+        bone: String,
+    },
+    UnmappedBone {
+        bone: String,
+    },
+    OptionalAttribute(Problem),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DegenerateBone { bone, policy } => {
+                write!(f, "the bone {bone} has zero determinant")?;
+                match policy {
+                    Policy::Skip => write!(f, ", skipped with its children"),
+                    Policy::Reparent => write!(f, ", its children are reparented"),
+                    Policy::Fail => Ok(()),
+                }
+            }
+            Self::OrphanedBone { bone, lost, policy } => {
+                write!(f, "the bone {bone} has lost its parent {lost}")?;
+                match policy {
+                    Policy::Skip => write!(f, ", skipped with its children"),
+                    Policy::Reparent => write!(f, ", reparented to the nearest bone"),
+                    Policy::Fail => Ok(()),
+                }
+            }
+            Self::NonUniformScale { bone, scale } => write!(
+                f,
+                "the bone {bone} has non-uniform scale {scale:?}, it can't be represented exactly",
+            ),
+            Self::UndefinedNode { node, ty } => {
+                write!(f, "the node {node} has undefined type {ty}, skipped")
+            }
+            Self::UnknownBone { bone } => write!(f, "the bone {bone} not found in the skeleton"),
+            Self::UnmappedBone { bone } => {
+                write!(f, "the bone {bone} has no match in the target skeleton")
+            }
+            Self::OptionalAttribute(problem) => write!(f, "{problem}"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::action::Axis};
+
+    fn node(ty: &str, name: &str, pos: [f32; 3], children: &str) -> String {
+        let [x, y, z] = pos;
+        format!(
+            "<node id=\"{name}\" name=\"{name}\" sid=\"{name}\" type=\"{ty}\">\
+            <matrix>1 0 0 {x} 0 1 0 {y} 0 0 1 {z} 0 0 0 1</matrix>{children}</node>"
+        )
+    }
+
+    fn scene(nodes: &str) -> String {
+        format!(
+            "<COLLADA><library_visual_scenes><visual_scene id=\"Scene\">{nodes}\
+            </visual_scene></library_visual_scenes></COLLADA>"
+        )
+    }
+
+    fn skeleton(src: &str) -> Skeleton {
+        skeleton_with(src, &Options::default())
+    }
+
+    fn skeleton_with(src: &str, opts: &Options) -> Skeleton {
+        let Ok(Output { elements, .. }) = parse(src, Target::Skeleton, opts) else {
+            panic!("failed to parse");
+        };
+
+        elements
+            .into_iter()
+            .find_map(|el| match el.val {
+                Value::Skeleton(sk) => Some(sk),
+                _ => None,
+            })
+            .expect("skeleton")
+    }
+
+    fn animation(id: &str, arrays: [&str; 5]) -> String {
+        let anim = animation_element(id, arrays);
+        format!("<COLLADA><library_animations>{anim}</library_animations></COLLADA>")
+    }
+
+    fn animation_element(id: &str, arrays: [&str; 5]) -> String {
+        let semantics = [
+            "INPUT",
+            "OUTPUT",
+            "INTERPOLATION",
+            "IN_TANGENT",
+            "OUT_TANGENT",
+        ];
+        let mut sources = String::new();
+        let mut inputs = String::new();
+        for (semantic, values) in semantics.into_iter().zip(arrays) {
+            let count = values.split_whitespace().count();
+            let array = match semantic {
+                "INTERPOLATION" => "Name_array",
+                _ => "float_array",
+            };
+
+            sources += &format!(
+                "<source id=\"{id}-{semantic}\">\
+                <{array} count=\"{count}\">{values}</{array}></source>"
+            );
+
+            inputs += &format!("<input semantic=\"{semantic}\" source=\"#{id}-{semantic}\"/>");
+        }
+
+        format!(
+            "<animation id=\"{id}\" name=\"Armature\">\
+            {sources}<sampler>{inputs}</sampler></animation>"
+        )
+    }
+
+    fn action(src: &str) -> Action {
+        let Ok(Output { elements, .. }) = parse(src, Target::Action, &Options::default()) else {
+            panic!("failed to parse");
+        };
+
+        elements
+            .into_iter()
+            .find_map(|el| match el.val {
+                Value::Action(act) => Some(act),
+                _ => None,
+            })
+            .expect("action")
+    }
+
+    #[test]
+    fn rotation_tangents() {
+        let src = animation(
+            "Armature_Action___Bone___rotation_euler_X",
+            [
+                "0 1",
+                "0 90",
+                "BEZIER BEZIER",
+                "-0.5 0 0.5 70",
+                "0.5 20 1.5 90",
+            ],
+        );
+
+        let act = action(&src);
+        let keys = &act.keyframes()["Bone"];
+        let handles = |idx: usize| {
+            let comp = keys[idx].val.rx.and_then(|comp| comp.int.handles());
+            comp.expect("handles")
+        };
+
+        // Tangent values are in degrees like outputs
+        assert_eq!(handles(0), [-0.5, 0., 0.5, 0.3491], "first key handles");
+        assert_eq!(handles(1), [-0.5, -0.3491, 0.5, 0.], "last key handles");
+    }
+
+    #[test]
+    fn intermediate_node() {
+        let child = node("JOINT", "Hand", [1., 0., 0.], "");
+        let helper = node("NODE", "Helper", [0., 0., 2.], &child);
+        let root = node("JOINT", "Arm", [0., 1., 0.], &helper);
+        let sk = skeleton(&scene(&node("NODE", "Armature", [0.; 3], &root)));
+
+        let bones = sk.bones();
+        assert_eq!(bones.len(), 2, "the joint below the node is kept");
+        assert_eq!(bones[1].parent, Some(0));
+        assert_eq!(bones[1].pos, [1., 0., 2.], "the node transform is kept");
+    }
+
+    #[test]
+    fn unknown_bones() {
+        let sk = skeleton(&scene(&node("JOINT", "Arm", [0.; 3], "")));
+        let key = ["0", "0", "LINEAR", "0 0", "0 0"];
+        let anims: String = [
+            "Armature_Action___Bone___location_X",
+            "Cube_Spin_location_X",
+        ]
+        .into_iter()
+        .map(|id| animation_element(id, key))
+        .collect();
+
+        let src = format!("<COLLADA><library_animations>{anims}</library_animations></COLLADA>");
+        let opts = Options {
+            skeleton: Some(sk),
+            ..Options::default()
+        };
+
+        let Ok(Output { warnings, .. }) = parse(&src, Target::Action, &opts) else {
+            panic!("failed to parse");
+        };
+
+        assert!(
+            matches!(&warnings[..], [Warning::UnknownBone { bone }] if bone == "Bone"),
+            "only the missing bone is reported",
+        );
+    }
+
+    #[test]
+    fn additive_reference() {
+        let anims: String = [("Idle", "1"), ("Breath", "3")]
+            .into_iter()
+            .map(|(name, x)| {
+                let id = format!("Armature_{name}___Bone___location_X");
+                animation_element(&id, ["0", x, "LINEAR", "0 0", "0 0"])
+            })
+            .collect();
+
+        let src = format!("<COLLADA><library_animations>{anims}</library_animations></COLLADA>");
+        let opts = Options {
+            mirror: Some(Axis::X),
+            additive: Some(Reference::Action("Idle".to_owned())),
+            ..Options::default()
+        };
+
+        let Ok(Output { elements, .. }) = parse(&src, Target::Action, &opts) else {
+            panic!("failed to parse");
+        };
+
+        let actions: BTreeMap<_, _> = elements
+            .iter()
+            .filter_map(|el| match &el.val {
+                Value::Action(act) => Some((el.name.as_str(), act)),
+                _ => None,
+            })
+            .collect();
+
+        let lx = |name: &str| {
+            let keys = &actions[name].keyframes()["Bone"];
+            keys[0].val.lx.map(|comp| comp.output)
+        };
+
+        assert!(!actions["Idle"].is_additive(), "the reference is kept");
+        assert!(
+            !actions["Idle_mirrored"].is_additive(),
+            "the mirrored reference"
+        );
+        assert!(actions["Breath"].is_additive(), "additive action");
+        assert!(
+            actions["Breath_mirrored"].is_additive(),
+            "mirrored additive action"
+        );
+        assert_eq!(lx("Breath"), Some(2.), "the reference is subtracted");
+        assert_eq!(
+            lx("Breath_mirrored"),
+            Some(-2.),
+            "the mirrored reference is subtracted"
+        );
+    }
+
+    #[test]
+    fn all_targets() {
+        let geometry = "<library_geometries><geometry id=\"Cube-mesh\" name=\"Cube\"><mesh>\
+            <source id=\"pos\"><float_array count=\"9\">0 0 0 1 0 0 0 1 0</float_array>\
+            <technique_common><accessor count=\"3\" stride=\"3\"><param name=\"X\"/>\
+            <param name=\"Y\"/><param name=\"Z\"/></accessor></technique_common></source>\
+            <source id=\"uv\"><float_array count=\"6\">0 0 1 0 0 1</float_array>\
+            <technique_common><accessor count=\"3\" stride=\"2\"><param name=\"S\"/>\
+            <param name=\"T\"/></accessor></technique_common></source>\
+            <vertices id=\"verts\"><input semantic=\"POSITION\" source=\"#pos\"/></vertices>\
+            <triangles count=\"1\"><input semantic=\"VERTEX\" source=\"#verts\" offset=\"0\"/>\
+            <input semantic=\"TEXCOORD\" source=\"#uv\" offset=\"1\"/><p>0 0 1 1 2 2</p>\
+            </triangles></mesh></geometry></library_geometries>";
+
+        let nodes = node("JOINT", "Bone", [0.; 3], "");
+        let key = ["0", "1", "LINEAR", "0 0", "0 0"];
+        let anims: String = [
+            "Armature_Action___Bone___location_X",
+            "Cube_Spin_location_X",
+        ]
+        .into_iter()
+        .map(|id| animation_element(id, key))
+        .collect();
+
+        let scene = scene(&nodes);
+        let scene = scene
+            .strip_prefix("<COLLADA>")
+            .and_then(|scene| scene.strip_suffix("</COLLADA>"))
+            .expect("scene");
+
+        let src = format!(
+            "<COLLADA>{geometry}{scene}<library_animations>{anims}\
+            </library_animations></COLLADA>"
+        );
+
+        let elements = |target| {
+            let Ok(Output { elements, .. }) = parse(&src, target, &Options::default()) else {
+                panic!("failed to parse");
+            };
+
+            let mut elements: Vec<_> = elements
+                .into_iter()
+                .map(|el| (el.val.kind(), el.name))
+                .collect();
+
+            elements.sort();
+            elements
+        };
+
+        let mut separate: Vec<_> = [Target::Mesh, Target::Skeleton, Target::Action]
+            .into_iter()
+            .flat_map(elements)
+            .collect();
+
+        separate.sort();
+        assert_eq!(elements(Target::All), separate, "all targets at once");
+        assert_eq!(
+            separate.iter().map(|&(kind, _)| kind).collect::<Vec<_>>(),
+            ["action", "mesh", "object", "skeleton"],
+            "every kind is converted",
+        );
+    }
+
+    #[test]
+    fn computed_binds() {
+        let child = node("JOINT", "Hand", [1., 0., 0.], "");
+        let root = node("JOINT", "Arm", [0., 1., 0.], &child);
+        let binds = Options {
+            bind: true,
+            ..Options::default()
+        };
+
+        let sk = skeleton_with(
+            &scene(&node("NODE", "Armature", [0., 0., 5.], &root)),
+            &binds,
+        );
+
+        let translation = |bind: Option<[f32; 16]>| {
+            let bind = bind.expect("bind");
+            [bind[12], bind[13], bind[14]]
+        };
+
+        // The armature object is not in the skeleton space
+        let bones = sk.bones();
+        assert_eq!(translation(bones[0].bind), [0., 1., 0.], "root bind");
+        assert_eq!(translation(bones[1].bind), [1., 1., 0.], "child bind");
+        assert_eq!(
+            translation(bones[1].inv_bind),
+            [-1., -1., 0.],
+            "inverse bind"
+        );
+    }
+
+    #[test]
+    fn skin_binds() {
+        let controllers = "<library_controllers><controller id=\"skin\"><skin>\
+            <source id=\"joints\"><Name_array count=\"1\">Arm</Name_array></source>\
+            <source id=\"binds\"><float_array count=\"16\">\
+            1 0 0 0 0 1 0 -2 0 0 1 0 0 0 0 1</float_array></source>\
+            <joints><input semantic=\"JOINT\" source=\"#joints\"/>\
+            <input semantic=\"INV_BIND_MATRIX\" source=\"#binds\"/></joints>\
+            </skin></controller></library_controllers>";
+
+        let root = node("JOINT", "Arm", [0., 1., 0.], "");
+        let scene = scene(&node("NODE", "Armature", [0., 0., 5.], &root));
+        let src = scene.replacen("<COLLADA>", &format!("<COLLADA>{controllers}"), 1);
+        let binds = Options {
+            bind: true,
+            ..Options::default()
+        };
+
+        let sk = skeleton_with(&src, &binds);
+
+        let bone = &sk.bones()[0];
+        let bind = bone.bind.expect("bind");
+        let inv = bone.inv_bind.expect("inverse bind");
+        assert_eq!(
+            [bind[12], bind[13], bind[14]],
+            [0., 2., 0.],
+            "bind of the skin"
+        );
+        assert_eq!(
+            [inv[12], inv[13], inv[14]],
+            [0., -2., 0.],
+            "inverse bind of the skin"
+        );
+    }
+
+    #[test]
+    fn bone_scale() {
+        let joint = |name: &str, [x, y, z]: [f32; 3]| {
+            format!(
+                "<node id=\"{name}\" name=\"{name}\" type=\"JOINT\">\
+                <matrix>{x} 0 0 0 0 {y} 0 0 0 0 {z} 0 0 0 0 1</matrix></node>"
+            )
+        };
+
+        let nodes = [
+            joint("Uniform", [2., 2., 2.]),
+            joint("Stretched", [2., 1., 1.]),
+            joint("Plain", [1., 1., 1.]),
+        ];
+
+        let src = scene(&nodes.concat());
+        let Ok(Output {
+            elements, warnings, ..
+        }) = parse(&src, Target::Skeleton, &Options::default())
+        else {
+            panic!("failed to parse");
+        };
+
+        let scales: Vec<_> = elements
+            .iter()
+            .filter_map(|el| match &el.val {
+                Value::Skeleton(sk) => Some(sk.bones()[0].scale),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            scales,
+            [Some([2., 2., 2.]), Some([2., 1., 1.]), None],
+            "only non-identity scale is kept",
+        );
+        assert!(
+            matches!(
+                &warnings[..],
+                [Warning::NonUniformScale { bone, scale: [2., 1., 1.] }] if bone == "Stretched",
+            ),
+            "non-uniform scale is reported",
+        );
+    }
+
+    fn degenerate_scene() -> String {
+        let child = node("JOINT", "Hand", [1., 0., 0.], "");
+        let bad = format!(
+            "<node id=\"Bad\" name=\"Bad\" type=\"JOINT\">\
+            <matrix>0 0 0 0 0 0 0 0 0 0 0 3 0 0 0 1</matrix>{child}</node>"
+        );
+
+        scene(&node("JOINT", "Arm", [0., 1., 0.], &bad))
+    }
+
+    fn parse_policy(policy: Policy) -> Result<Output, Error> {
+        let opts = Options {
+            bind: true,
+            policy,
+            ..Options::default()
+        };
+
+        parse(&degenerate_scene(), Target::Skeleton, &opts)
+    }
+
+    fn bone_names(elements: &[Element]) -> Vec<&str> {
+        elements
+            .iter()
+            .filter_map(|el| match &el.val {
+                Value::Skeleton(sk) => Some(sk.bones()),
+                _ => None,
+            })
+            .flatten()
+            .map(|bone| bone.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn skip_policy() {
+        let Ok(Output {
+            elements, warnings, ..
+        }) = parse_policy(Policy::Skip)
+        else {
+            panic!("failed to parse");
+        };
+
+        assert_eq!(bone_names(&elements), ["Arm"], "skipped with children");
+        assert!(
+            matches!(
+                &warnings[..],
+                [Warning::DegenerateBone { bone, policy: Policy::Skip }] if bone == "Bad",
+            ),
+            "the degenerate bone is reported",
+        );
+    }
+
+    #[test]
+    fn reparent_policy() {
+        let Ok(Output {
+            elements, warnings, ..
+        }) = parse_policy(Policy::Reparent)
+        else {
+            panic!("failed to parse");
+        };
+
+        assert_eq!(bone_names(&elements), ["Arm", "Hand"], "the child is kept");
+        assert!(
+            matches!(
+                &warnings[..],
+                [
+                    Warning::DegenerateBone { bone, policy: Policy::Reparent },
+                    Warning::OrphanedBone { bone: orphan, lost, policy: Policy::Reparent },
+                ] if bone == "Bad" && orphan == "Hand" && lost == "Bad",
+            ),
+            "degenerate and orphaned bones are reported",
+        );
+
+        let Some(Value::Skeleton(sk)) = elements.first().map(|el| &el.val) else {
+            panic!("expected skeleton");
+        };
+
+        let hand = &sk.bones()[1];
+        assert_eq!(hand.parent, Some(0), "reparented to the nearest bone");
+        assert_eq!(hand.pos, [1., 0., 3.], "the translation is kept");
+
+        let bind = hand.bind.expect("bind");
+        let inv = hand.inv_bind.expect("inverse bind");
+        assert_eq!([bind[12], bind[13], bind[14]], [1., 1., 3.], "bind");
+        assert!(
+            inv.iter().all(|v| v.is_finite()),
+            "the inverse bind is finite"
+        );
+    }
+
+    #[test]
+    fn fail_policy() {
+        assert!(
+            matches!(parse_policy(Policy::Fail), Err(Error::DegenerateBone(bone)) if bone == "Bad"),
+            "the degenerate bone fails the conversion",
+        );
+    }
+
+    #[test]
+    fn euler_keyed_apart() {
+        let anims = [
+            ("X", ["0 4", "0 80", "LINEAR LINEAR", "0 0 0 0", "0 0 0 0"]),
+            ("Y", ["1", "20", "LINEAR", "0 0", "0 0"]),
+        ]
+        .map(|(axis, arrays)| {
+            animation_element(
+                &format!("Armature_Action___Bone___rotation_euler_{axis}"),
+                arrays,
+            )
+        });
+
+        let src = format!(
+            "<COLLADA><library_animations>{}</library_animations></COLLADA>",
+            anims.concat(),
+        );
+
+        let act = action(&src);
+        let eulers: Vec<_> = act.keyframes()["Bone"]
+            .iter()
+            .map(|key| (key.input, key.val.euler()))
+            .collect();
+
+        // Missing axes are taken from their own curves
+        assert_eq!(
+            eulers,
+            [
+                (0., Some([0., 0.3491, 0.])),
+                (1., Some([0.3491, 0.3491, 0.])),
+                (4., Some([1.3963, 0.3491, 0.])),
+            ],
+            "euler angles keyed at different times",
+        );
+    }
+
+    fn action_ranges(src: &str) -> Vec<(String, [f32; 2])> {
+        let Ok(Output { elements, .. }) = parse(src, Target::Action, &Options::default()) else {
+            panic!("failed to parse");
+        };
+
+        elements
+            .into_iter()
+            .filter_map(|el| match el.val {
+                Value::Action(act) => {
+                    let keys = &act.keyframes()["Bone"];
+                    let range = [keys.first()?.input, keys.last()?.input];
+                    Some((el.name, range))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_actions() {
+        let anims = |ids: [&str; 2]| {
+            let [walk, run] = [(ids[0], "0 1"), (ids[1], "2 3")].map(|(id, input)| {
+                animation_element(id, [input, "0 1", "LINEAR LINEAR", "0 0 0 0", "0 0 0 0"])
+            });
+
+            format!("<library_animations>{walk}{run}</library_animations>")
+        };
+
+        let by_ids = anims([
+            "Armature_Walk___Bone___location_X",
+            "Armature_Run___Bone___location_X",
+        ]);
+
+        assert_eq!(
+            action_ranges(&format!("<COLLADA>{by_ids}</COLLADA>")),
+            [("Run".to_owned(), [2., 3.]), ("Walk".to_owned(), [0., 1.])],
+            "actions are split by id prefixes",
+        );
+
+        let clips = "<library_animation_clips>\
+            <animation_clip id=\"idle\" name=\"Idle\"><instance_animation url=\"#a\"/></animation_clip>\
+            <animation_clip id=\"jump\" name=\"Jump\"><instance_animation url=\"#b\"/></animation_clip>\
+            </library_animation_clips>";
+
+        // Blender groups animations of a clip in a container referenced by the clip
+        let [idle, jump] = [("a", "X", "0 1"), ("b", "Y", "2 3")].map(|(id, axis, input)| {
+            let anim = animation_element(
+                &format!("Armature_Action___Bone___location_{axis}"),
+                [input, "0 1", "LINEAR LINEAR", "0 0 0 0", "0 0 0 0"],
+            );
+
+            format!("<animation id=\"{id}\" name=\"Armature\">{anim}</animation>")
+        });
+
+        let by_clips = format!("<library_animations>{idle}{jump}</library_animations>");
+        assert_eq!(
+            action_ranges(&format!("<COLLADA>{by_clips}{clips}</COLLADA>")),
+            [("Idle".to_owned(), [0., 1.]), ("Jump".to_owned(), [2., 3.])],
+            "actions are split by clips",
+        );
+    }
+
+    fn curve(names: &str, arrays: [&str; 4]) -> Vec<(f32, f32, Option<[f32; 4]>)> {
+        let [input, output, intangent, outtangent] = arrays;
+        let src = animation(
+            "Armature_Action___Bone___location_X",
+            [input, output, names, intangent, outtangent],
+        );
+
+        action(&src).keyframes()["Bone"]
+            .iter()
+            .filter_map(|key| {
+                let comp = key.val.lx?;
+                Some((key.input, comp.output, comp.int.handles()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hermite() {
+        let keys = curve("HERMITE HERMITE", ["0 1", "0 2", "3 0 3 3", "3 3 3 0"]);
+
+        // Tangent vectors are a third of bezier handles
+        assert_eq!(
+            keys,
+            [
+                (0., 0., Some([-1., 0., 1., 1.])),
+                (1., 2., Some([-1., -1., 1., 0.])),
+            ],
+            "hermite keys",
+        );
+    }
+
+    #[test]
+    fn cardinal() {
+        let keys = curve(
+            "CARDINAL CARDINAL CARDINAL",
+            ["0 1 2", "0 3 0", "0 0 0 0 0 0", "0 0 0 0 0 0"],
+        );
+
+        // Tangents are taken from neighbor keys
+        assert_eq!(
+            keys,
+            [
+                (0., 0., Some([-0.3333, -1., 0.3333, 1.])),
+                (1., 3., Some([-0.3333, 0., 0.3333, 0.])),
+                (2., 0., Some([-0.3333, 1., 0.3333, -1.])),
+            ],
+            "cardinal keys",
+        );
+    }
+
+    #[test]
+    fn bspline() {
+        let keys = curve(
+            "BSPLINE BSPLINE BSPLINE",
+            ["0 1 2", "0 6 0", "0 0 0 0 0 0", "0 0 0 0 0 0"],
+        );
+
+        let points: Vec<_> = keys.iter().map(|&(x, y, _)| (x, y)).collect();
+        assert_eq!(
+            points,
+            [(0., 0.), (0.1667, 1.), (1., 4.), (1.8333, 1.), (2., 0.)],
+            "the curve starts and ends at the end points",
+        );
+        assert_eq!(keys[0].2, Some([0.; 4]), "the first key is flat");
+        assert_eq!(
+            keys[1].2,
+            Some([-0.1667, -1., 0.1667, 1.]),
+            "handles of the inner key",
+        );
+    }
+}
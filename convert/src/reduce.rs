@@ -0,0 +1,129 @@
+use {
+    crate::action::{self, Action, Get, Keyframe, Kind, CHANNELS},
+    std::collections::BTreeMap,
+};
+
+/// Maximum errors allowed when removing keys.
+#[derive(Clone, Copy)]
+pub struct Tolerance {
+    /// Maximum rotation error in radians.
+    pub angular: f32,
+
+    /// Maximum location and scale error.
+    pub positional: f32,
+}
+
+/// Removes keys of the action that interpolation can reconstruct within the tolerance.
+///
+/// Returns the number of removed keys for each bone.
+pub fn reduce(action: &mut Action, tol: Tolerance) -> BTreeMap<String, usize> {
+    action
+        .keyframes_mut()
+        .iter_mut()
+        .map(|(bone, keys)| {
+            let len = keys.len();
+            *keys = reduce_keys(keys, tol);
+            (bone.clone(), len - keys.len())
+        })
+        .collect()
+}
+
+/// How many points of a segment are checked.
+const SAMPLES: usize = 8;
+
+fn reduce_keys(keys: &[Keyframe], tol: Tolerance) -> Vec<Keyframe> {
+    let Some((&first, rest)) = keys.split_first() else {
+        return vec![];
+    };
+
+    let mut out = vec![first];
+    for (idx, &key) in rest.iter().enumerate() {
+        // The last key always stays
+        let Some(&next) = rest.get(idx + 1) else {
+            out.push(key);
+            break;
+        };
+
+        let &last = out.last().unwrap_or(&first);
+        let start = keys.partition_point(|k| k.input < last.input);
+        let end = keys.partition_point(|k| k.input <= next.input);
+        let original = &keys[start..end];
+        let reduced = [last, next];
+        // Check the removed keys themselves so a short spike can't slip between samples
+        let samples = (0..=SAMPLES).map(|i| {
+            let t = i as f32 / SAMPLES as f32;
+            last.input + (next.input - last.input) * t
+        });
+
+        let mut inputs = samples.chain(original.iter().map(|k| k.input));
+        let fits = inputs.all(|input| {
+            CHANNELS.iter().all(|&(_, get, _, kind)| {
+                let a = eval(original, get, input);
+                let b = eval(&reduced, get, input);
+                let tol = match kind {
+                    Kind::Angle => tol.angular,
+                    // Quaternion components change about half as much as the angle
+                    Kind::Quaternion => tol.angular / 2.,
+                    Kind::Position => tol.positional,
+                };
+
+                match (a, b) {
+                    (Some(a), Some(b)) => (a - b).abs() <= tol,
+                    (None, None) => true,
+                    _ => false,
+                }
+            })
+        });
+
+        if !fits {
+            out.push(key);
+        }
+    }
+
+    out
+}
+
+fn eval(keys: &[Keyframe], get: Get, input: f32) -> Option<f32> {
+    let curve: Vec<_> = keys
+        .iter()
+        .filter_map(|key| Some((key.input, get(&key.val)?)))
+        .collect();
+
+    action::eval_curve(&curve, input)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::action::{Channel, Component, Interpolation},
+    };
+
+    #[test]
+    fn spike() {
+        let key = |output| Component {
+            output,
+            int: Interpolation::Linear,
+        };
+
+        let mut action = Action::default();
+        let chans = [
+            (0., Channel::LocationX(key(0.))),
+            // Falls between the samples of the whole segment
+            (0.5625, Channel::LocationX(key(0.011))),
+            (1., Channel::LocationX(key(0.))),
+        ];
+
+        for (input, chan) in chans {
+            action.insert_channel("bone".to_owned(), input, chan);
+        }
+
+        let tol = Tolerance {
+            angular: 0.01,
+            positional: 0.01,
+        };
+
+        let removed = reduce(&mut action, tol);
+        assert_eq!(removed["bone"], 0, "the spike key survives");
+    }
+}
@@ -0,0 +1,144 @@
+use {
+    crate::{
+        action::{self, Action, Keyframe, Order, Value},
+        parser::{self, Warning},
+        skeleton::Skeleton,
+    },
+    std::collections::HashMap,
+};
+
+/// Re-expresses the action of the `source` skeleton for the `target` one.
+///
+/// Bones are matched by the `names` mapping from source to target bone names,
+/// unmapped bones keep their names. Since rotations of an action are relative
+/// to the bone's rest pose, each rotation is transformed from the source rest
+/// pose to the target one. Euler rotations use the `orders` of source bones,
+/// XYZ by default. Bones that can't be found in any of the skeletons
+/// are dropped and reported as [warnings](Warning).
+pub fn retarget(
+    action: &Action,
+    source: &Skeleton,
+    target: &Skeleton,
+    names: &HashMap<String, String>,
+    orders: &HashMap<String, Order>,
+    warnings: &mut Vec<Warning>,
+) -> Action {
+    parser::init_params();
+
+    let source_rots = source.world_rotations();
+    let target_rots = target.world_rotations();
+    let mut out = Action::default();
+    for (bone, keys) in action.keyframes() {
+        let Some(source_idx) = source.resolve(bone) else {
+            warnings.push(Warning::UnknownBone { bone: bone.clone() });
+            continue;
+        };
+
+        let target_name = names.get(bone).map_or(bone.as_str(), String::as_str);
+        let Some(target_idx) = target.resolve(target_name) else {
+            warnings.push(Warning::UnmappedBone { bone: bone.clone() });
+            continue;
+        };
+
+        // The rotation from the source rest pose to the target one
+        let rest =
+            target_rots[usize::from(target_idx)].inverse() * source_rots[usize::from(source_idx)];
+
+        let mut keys = keys.clone();
+        action::fill_locations(&mut keys);
+
+        let order = orders.get(bone).copied().unwrap_or_default();
+        let mut near = Value::default();
+        let keys = keys
+            .iter()
+            .map(|&Keyframe { input, val }| {
+                let val = val
+                    .map_rotation(order, &near, |rot| rest * rot * rest.inverse())
+                    .map_location(|loc| rest * loc);
+
+                near = val;
+                Keyframe { input, val }
+            })
+            .collect();
+
+        let name = &target.bones()[usize::from(target_idx)].name;
+        out.insert_keys(name.clone(), keys);
+    }
+
+    out.round();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            action::{Channel, Component, Interpolation, Rotation},
+            skeleton::Bone,
+        },
+        glam::{Quat, Vec3},
+        std::f32::consts::FRAC_PI_2,
+    };
+
+    fn skeleton(rot: Quat) -> Skeleton {
+        let bone = Bone {
+            name: "Arm".to_owned(),
+            pos: [0.; 3],
+            rot: rot.to_array(),
+            scale: None,
+            parent: None,
+            bind: None,
+            inv_bind: None,
+        };
+
+        Skeleton::from_bones(vec![bone]).expect("skeleton")
+    }
+
+    #[test]
+    fn different_rests() {
+        parser::init_params();
+
+        let comp = |output| Component {
+            output,
+            int: Interpolation::Linear,
+        };
+
+        let [qx, qy, qz, qw] = Quat::from_rotation_x(FRAC_PI_2).to_array();
+        let mut action = Action::default();
+        for chan in [
+            Channel::QuaternionX(comp(qx)),
+            Channel::QuaternionY(comp(qy)),
+            Channel::QuaternionZ(comp(qz)),
+            Channel::QuaternionW(comp(qw)),
+            Channel::LocationX(comp(1.)),
+            Channel::LocationY(comp(0.)),
+            Channel::LocationZ(comp(0.)),
+        ] {
+            action.insert_channel("Arm".to_owned(), 0., chan);
+        }
+
+        // The target bone rests turned around Z, so the same
+        // motion turns it around its own -Y axis
+        let source = skeleton(Quat::IDENTITY);
+        let target = skeleton(Quat::from_rotation_z(FRAC_PI_2));
+        let mut warnings = vec![];
+        let (names, orders) = (HashMap::new(), HashMap::new());
+        let out = retarget(&action, &source, &target, &names, &orders, &mut warnings);
+        assert!(warnings.is_empty(), "all bones are found");
+
+        let pose = out.sample("Arm", 0.).expect("pose");
+        let Rotation::Quat(rot) = pose.rot else {
+            panic!("expected a quaternion");
+        };
+
+        let expected = Quat::from_rotation_y(-FRAC_PI_2);
+        assert!(
+            Quat::from_array(rot).abs_diff_eq(expected, 1e-3),
+            "rotation {rot:?}"
+        );
+
+        let pos = Vec3::from_array(pose.pos);
+        assert!(pos.abs_diff_eq(Vec3::NEG_Y, 1e-3), "location {pos}");
+    }
+}
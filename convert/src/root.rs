@@ -0,0 +1,153 @@
+use {
+    crate::{
+        action::{Action, Axis, Keyframe, Order, Value},
+        skeleton,
+    },
+    glam::{Quat, Vec3},
+    std::{collections::HashMap, error, fmt, str},
+};
+
+/// Root motion extraction parameters.
+#[derive(Clone, Default)]
+pub struct RootMotion {
+    /// The root bone name, the top bone of the skeleton by default.
+    pub bone: Option<String>,
+
+    /// Axes to extract.
+    pub axes: Axes,
+}
+
+/// Location and rotation axes of root motion in the root bone space.
+#[derive(Clone, Copy, Default)]
+pub struct Axes {
+    pub location: [bool; 3],
+    pub rotation: Option<Axis>,
+}
+
+impl str::FromStr for Axes {
+    type Err = InvalidAxes;
+
+    /// Parses comma separated channels, such as `lx,lz,ry`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut axes = Self::default();
+        for chan in s.split(',').map(str::trim) {
+            match chan {
+                "lx" => axes.location[0] = true,
+                "ly" => axes.location[1] = true,
+                "lz" => axes.location[2] = true,
+                "rx" | "ry" | "rz" if axes.rotation.is_some() => return Err(InvalidAxes),
+                "rx" => axes.rotation = Some(Axis::X),
+                "ry" => axes.rotation = Some(Axis::Y),
+                "rz" => axes.rotation = Some(Axis::Z),
+                _ => return Err(InvalidAxes),
+            }
+        }
+
+        Ok(axes)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidAxes;
+
+impl fmt::Display for InvalidAxes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid axes, expected lx, ly, lz and one of rx, ry, rz")
+    }
+}
+
+impl error::Error for InvalidAxes {}
+
+/// Takes root motion out of the action.
+///
+/// Returns the root motion track with extracted channels of the root bone.
+/// The rotation around the axis is separated with swing-twist decomposition.
+pub(crate) fn extract(
+    action: &mut Action,
+    bone: &str,
+    axes: Axes,
+    orders: &HashMap<String, Order>,
+) -> Option<Action> {
+    let (bone, keys) = action
+        .keyframes_mut()
+        .iter_mut()
+        .find(|(name, _)| skeleton::matches(bone, name))?;
+
+    let order = orders.get(bone).copied().unwrap_or_default();
+
+    let mut track = Vec::with_capacity(keys.len());
+    let (mut near, mut near_track) = (Value::default(), Value::default());
+    for key in keys.iter_mut() {
+        let mut val = key.val;
+        let mut motion = Value::default();
+        let locs = [
+            (&mut val.lx, &mut motion.lx),
+            (&mut val.ly, &mut motion.ly),
+            (&mut val.lz, &mut motion.lz),
+        ];
+
+        for ((from, to), extract) in locs.into_iter().zip(axes.location) {
+            if extract {
+                *to = from.take();
+            }
+        }
+
+        if let Some(axis) = axes.rotation {
+            let axis = axis.vec();
+            let rot = key.val.map_rotation(order, &near_track, |q| twist(q, axis));
+            (motion.rx, motion.ry, motion.rz) = (rot.rx, rot.ry, rot.rz);
+            (motion.qw, motion.qx, motion.qy, motion.qz) = (rot.qw, rot.qx, rot.qy, rot.qz);
+            val = val.map_rotation(order, &near, |q| twist(q, axis).inverse() * q);
+        }
+
+        (near, near_track) = (val, motion);
+        key.val = val;
+        if motion.components_mut().next().is_some() {
+            track.push(Keyframe {
+                input: key.input,
+                val: motion,
+            });
+        }
+    }
+
+    let mut out = Action::default();
+    out.insert_keys(bone.clone(), track);
+    Some(out)
+}
+
+/// Returns the rotation part around the axis.
+fn twist(q: Quat, axis: Vec3) -> Quat {
+    let proj = axis * Vec3::new(q.x, q.y, q.z).dot(axis);
+    let twist = Quat::from_xyzw(proj.x, proj.y, proj.z, q.w);
+    if twist.length_squared() < f32::EPSILON {
+        Quat::IDENTITY
+    } else {
+        twist.normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_axes() {
+        let axes: Axes = "lx,lz,ry".parse().expect("axes");
+        assert_eq!(axes.location, [true, false, true]);
+        assert!(matches!(axes.rotation, Some(Axis::Y)));
+        assert!("rx,ry".parse::<Axes>().is_err(), "one rotation axis");
+        assert!("x".parse::<Axes>().is_err(), "unknown channel");
+    }
+
+    #[test]
+    fn swing_twist() {
+        let yaw = Quat::from_rotation_y(0.7);
+        let swing = Quat::from_rotation_x(0.3);
+        let t = twist(yaw * swing, Vec3::Y);
+        assert!(t.abs_diff_eq(yaw, 1e-2), "twist is close to yaw");
+        assert!(
+            (t.inverse() * yaw * swing).abs_diff_eq(swing, 1e-2),
+            "swing"
+        );
+    }
+}
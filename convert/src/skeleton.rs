@@ -1,5 +1,6 @@
 use {
-    serde::Serialize,
+    glam::Quat,
+    serde::{Deserialize, Serialize},
     std::{collections::HashMap, fmt},
 };
 
@@ -10,42 +11,110 @@ pub struct Skeleton {
 }
 
 impl Skeleton {
-    pub(crate) fn push(&mut self, bone: Bone) -> Result<(), ToManyBones> {
-        let idx = self.bones.len().try_into().map_err(|_| ToManyBones)?;
-        assert!(bone.parent < Some(idx), "parent nodes must come first");
+    /// Creates a skeleton from bones, for example, read from a converted `.json` file.
+    ///
+    /// # Errors
+    /// Returns an [error](Error) if there are too many bones
+    /// or some parent comes after its child.
+    pub fn from_bones(bones: Vec<Bone>) -> Result<Self, Error> {
+        let mut sk = Self::default();
+        for bone in bones {
+            sk.push(bone)?;
+        }
+
+        Ok(sk)
+    }
+
+    pub(crate) fn push(&mut self, bone: Bone) -> Result<u16, Error> {
+        let idx = self
+            .bones
+            .len()
+            .try_into()
+            .map_err(|_| Error::ToManyBones)?;
+        if bone.parent >= Some(idx) {
+            return Err(Error::ParentOrder(bone.name));
+        }
 
         self.names.insert(bone.name.clone(), idx);
         self.bones.push(bone);
-        Ok(())
+        Ok(idx)
     }
 
     pub(crate) fn is_empty(&self) -> bool {
         self.bones.is_empty()
     }
 
-    pub(crate) fn get(&self, name: &str) -> Option<u16> {
+    pub fn get(&self, name: &str) -> Option<u16> {
         self.names.get(name).copied()
     }
 
+    /// Finds a bone by its name or by the name with special characters
+    /// replaced by underscores, as Blender writes it in animation ids.
+    pub fn resolve(&self, name: &str) -> Option<u16> {
+        self.get(name).or_else(|| {
+            let idx = self
+                .bones
+                .iter()
+                .position(|bone| matches(&bone.name, name))?;
+            u16::try_from(idx).ok()
+        })
+    }
+
+    /// Returns rest rotations of all bones in the skeleton space.
+    pub(crate) fn world_rotations(&self) -> Vec<Quat> {
+        let mut rots: Vec<Quat> = Vec::with_capacity(self.bones.len());
+        for bone in &self.bones {
+            let local = Quat::from_array(bone.rot);
+            let rot = match bone.parent {
+                Some(parent) => rots[usize::from(parent)] * local,
+                None => local,
+            };
+
+            rots.push(rot);
+        }
+
+        rots
+    }
+
     pub fn bones(&self) -> &[Bone] {
         &self.bones
     }
 }
 
-pub struct ToManyBones;
+/// Checks the name is the bone name as is or with special characters
+/// replaced by underscores, as Blender writes it in animation ids.
+pub(crate) fn matches(bone: &str, name: &str) -> bool {
+    let sanitize = |c: char| if c.is_alphanumeric() { c } else { '_' };
+    bone == name || bone.chars().map(sanitize).eq(name.chars())
+}
 
-impl fmt::Display for ToManyBones {
+#[derive(Debug)]
+pub enum Error {
+    ToManyBones,
+    ParentOrder(String),
+}
+
+impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "to many bones")
+        match self {
+            Self::ToManyBones => write!(f, "to many bones"),
+            Self::ParentOrder(bone) => write!(f, "the parent of bone {bone} must come first"),
+        }
     }
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Bone {
     pub name: String,
     pub pos: [f32; 3],
     pub rot: [f32; 4],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<[f32; 3]>,
     pub parent: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind: Option<[f32; 16]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inv_bind: Option<[f32; 16]>,
 }
 
 #[cfg(test)]
@@ -62,13 +131,24 @@ mod tests {
         assert!(skeleton.push(Bone::with_parent(Some(2))).is_ok());
     }
 
+    #[test]
+    fn push_unordered() {
+        let mut skeleton = Skeleton::default();
+        assert!(skeleton.push(Bone::with_parent(None)).is_ok());
+        assert!(skeleton.push(Bone::with_parent(Some(1))).is_err());
+        assert!(skeleton.push(Bone::with_parent(Some(2))).is_err());
+    }
+
     impl Bone {
         fn with_parent(parent: Option<u16>) -> Self {
             Self {
                 name: String::default(),
                 pos: [0.; 3],
                 rot: [0.; 4],
+                scale: None,
                 parent,
+                bind: None,
+                inv_bind: None,
             }
         }
     }
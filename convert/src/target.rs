@@ -5,6 +5,9 @@ pub enum Target {
     Mesh,
     Skeleton,
     Action,
+
+    /// All kinds of elements at once.
+    All,
 }
 
 impl str::FromStr for Target {
@@ -15,11 +18,23 @@ impl str::FromStr for Target {
             "mesh" => Ok(Self::Mesh),
             "skeleton" => Ok(Self::Skeleton),
             "action" => Ok(Self::Action),
+            "all" => Ok(Self::All),
             _ => Err(Unknown),
         }
     }
 }
 
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mesh => write!(f, "mesh"),
+            Self::Skeleton => write!(f, "skeleton"),
+            Self::Action => write!(f, "action"),
+            Self::All => write!(f, "all"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Unknown;
 
@@ -0,0 +1,241 @@
+use {
+    crate::action::{Action, Component, Interpolation, Keyframe},
+    glam::Vec2,
+};
+
+/// Changes the playback speed of the action.
+///
+/// Key times and bezier handles are divided by the speed.
+pub fn scale_speed(action: &Action, speed: f32) -> Action {
+    let mut out = action.map_curves(|curve| {
+        curve
+            .into_iter()
+            .map(|(input, Component { output, int })| {
+                let int = match int {
+                    Interpolation::Bezier([lx, ly, rx, ry]) => {
+                        Interpolation::Bezier([lx / speed, ly, rx / speed, ry])
+                    }
+                    Interpolation::Hermite([ix, iy, ox, oy]) => {
+                        Interpolation::Hermite([ix / speed, iy, ox / speed, oy])
+                    }
+                    int => int,
+                };
+
+                (input / speed, Component { output, int })
+            })
+            .collect()
+    });
+
+    out.round();
+    out
+}
+
+/// Plays the action backwards within its time range.
+///
+/// A stepped segment keeps its value, so the key that now starts it takes
+/// the value of the segment if it doesn't end a smooth segment.
+pub fn reverse(action: &Action) -> Action {
+    let (start, end) = action.range().unwrap_or_default();
+    let mut out = action.map_curves(|curve| reverse_curve(&curve, start + end));
+    out.round();
+    out
+}
+
+/// Reverses the curve, so a key at `x` moves to `sum - x`.
+fn reverse_curve(curve: &[(f32, Component)], sum: f32) -> Vec<(f32, Component)> {
+    let mut points: Vec<_> = curve
+        .iter()
+        .rev()
+        .map(|&(input, comp)| (sum - input, comp.output))
+        .collect();
+
+    // Each segment was interpolated by its right key,
+    // handles are flipped in time
+    let flip = |v: Vec2| Vec2::new(-v.x, v.y);
+    let segments: Vec<_> = segments(curve)
+        .into_iter()
+        .rev()
+        .map(|seg| match seg {
+            Segment::Bezier(c1, c2) => Segment::Bezier(flip(c2), flip(c1)),
+            seg => seg,
+        })
+        .collect();
+
+    for idx in 0..segments.len() {
+        let prev_step = idx == 0 || matches!(segments[idx - 1], Segment::Step);
+        if matches!(segments[idx], Segment::Step) && prev_step {
+            points[idx].1 = points[idx + 1].1;
+        }
+    }
+
+    build(&points, &segments)
+}
+
+/// Makes the action loop seamlessly.
+///
+/// The first key of each curve is copied to one frame after the end of
+/// the action, unless all curves already end with their first values.
+/// The bezier tangent before the copied key is aligned to the one after
+/// the first key.
+pub fn make_loop(action: &Action, frame: f32) -> Action {
+    let (_, end) = action.range().unwrap_or_default();
+    let closed = action.keyframes().values().all(|keys| {
+        let (Some(first), Some(last)) = (keys.first(), keys.last()) else {
+            return true;
+        };
+
+        let outputs = |key: &Keyframe| {
+            let mut val = key.val;
+            let outputs: Vec<_> = val.components_mut().map(|comp| comp.output).collect();
+            outputs
+        };
+
+        last.input == end && outputs(first) == outputs(last)
+    });
+
+    let end = if closed { end } else { end + frame };
+    let mut out = action.map_curves(|curve| loop_curve(&curve, end));
+    out.round();
+    out
+}
+
+fn loop_curve(curve: &[(f32, Component)], end: f32) -> Vec<(f32, Component)> {
+    let (Some(&(x0, first)), Some(&(last_input, last))) = (curve.first(), curve.last()) else {
+        return vec![];
+    };
+
+    let mut points: Vec<_> = curve.iter().map(|&(x, comp)| (x, comp.output)).collect();
+    let mut segments = segments(curve);
+    if last_input == end {
+        if let Some(point) = points.last_mut() {
+            point.1 = first.output;
+        }
+    } else {
+        // The new segment is interpolated by the last key
+        segments.push(match last.int {
+            Interpolation::Step => Segment::Step,
+            Interpolation::Linear => Segment::Linear,
+            Interpolation::Bezier(_) | Interpolation::Hermite(_) => {
+                let [_, _, rx, ry] = last.int.handles().unwrap_or_default();
+                Segment::Bezier(Vec2::new(rx, ry), Vec2::ZERO)
+            }
+        });
+
+        points.push((end, first.output));
+    }
+
+    // The outgoing tangent of the first key
+    let tangent = match (segments.first(), points.get(1)) {
+        (Some(Segment::Bezier(c1, _)), _) => Some(*c1),
+        (Some(Segment::Linear), Some(&(x1, y1))) => {
+            Some(Vec2::new(x1 - x0, y1 - first.output) / 3.)
+        }
+        _ => None,
+    };
+
+    if let (Some(Segment::Bezier(_, c2)), Some(tangent)) = (segments.last_mut(), tangent) {
+        *c2 = -tangent;
+    }
+
+    build(&points, &segments)
+}
+
+#[derive(Clone, Copy)]
+enum Segment {
+    Step,
+    Linear,
+    /// Bezier control points relative to the left and right keys.
+    Bezier(Vec2, Vec2),
+}
+
+fn segments(curve: &[(f32, Component)]) -> Vec<Segment> {
+    curve
+        .windows(2)
+        .map(|pair| {
+            let [(_, k0), (_, k1)] = [pair[0], pair[1]];
+            match k0.int {
+                Interpolation::Step => Segment::Step,
+                Interpolation::Linear => Segment::Linear,
+                Interpolation::Bezier(_) | Interpolation::Hermite(_) => {
+                    let [_, _, rx, ry] = k0.int.handles().unwrap_or_default();
+                    let [lx, ly, _, _] = k1.int.handles().unwrap_or_default();
+                    Segment::Bezier(Vec2::new(rx, ry), Vec2::new(lx, ly))
+                }
+            }
+        })
+        .collect()
+}
+
+/// Builds a curve from key points and segments between them.
+///
+/// Since a key keeps its left handle only with bezier interpolation,
+/// a linear segment after a bezier one is written as a straight bezier.
+fn build(points: &[(f32, f32)], segments: &[Segment]) -> Vec<(f32, Component)> {
+    let bezier = |left: Option<Vec2>, right: Vec2| {
+        let left = left.unwrap_or_default();
+        Interpolation::Bezier([left.x, left.y, right.x, right.y])
+    };
+
+    let mut out = Vec::with_capacity(points.len());
+    let mut left = None;
+    for (idx, &(input, output)) in points.iter().enumerate() {
+        let (int, next_left) = match (segments.get(idx), points.get(idx + 1)) {
+            (Some(&Segment::Bezier(c1, c2)), _) => (bezier(left, c1), Some(c2)),
+            (Some(Segment::Linear), Some(&(x1, y1))) if left.is_some() => {
+                let d = Vec2::new(x1 - input, y1 - output) / 3.;
+                (bezier(left, d), Some(-d))
+            }
+            (Some(Segment::Step), _) => (Interpolation::Step, None),
+            _ if left.is_some() => (bezier(left, Vec2::ZERO), None),
+            _ => (Interpolation::Linear, None),
+        };
+
+        out.push((input, Component { output, int }));
+        left = next_left;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::action};
+
+    fn curve() -> Vec<(f32, Component)> {
+        let key = |output, int| Component { output, int };
+        vec![
+            (0., key(0., Interpolation::Linear)),
+            (1., key(2., Interpolation::Bezier([-0.3, 0., 0.3, 1.]))),
+            (2., key(4., Interpolation::Bezier([-0.3, -1., 0., 0.]))),
+        ]
+    }
+
+    #[test]
+    fn reversed() {
+        let curve = curve();
+        let reversed = reverse_curve(&curve, 2.);
+        for i in 0..=20 {
+            let input = i as f32 / 10.;
+            let a = action::eval_curve(&curve, input).expect("value");
+            let b = action::eval_curve(&reversed, 2. - input).expect("value");
+            assert!((a - b).abs() < 1e-3, "same value at {input}: {a} != {b}");
+        }
+    }
+
+    #[test]
+    fn looped() {
+        let looped = loop_curve(&curve(), 3.);
+        assert_eq!(looped.len(), 4, "the first key is copied");
+        assert_eq!(looped.last().map(|&(x, c)| (x, c.output)), Some((3., 0.)));
+
+        // The first segment is linear, so its tangent goes to the next key
+        let [(x0, k0), (x1, k1)] = [looped[0], looped[1]];
+        let tangent = Vec2::new(x1 - x0, k1.output - k0.output) / 3.;
+        let (_, seam) = looped[3];
+        let [lx, ly, ..] = seam.int.handles().expect("bezier seam");
+        assert!(
+            (Vec2::new(lx, ly) + tangent).length() < 1e-3,
+            "the seam tangent {lx} {ly} is aligned to {tangent}",
+        );
+    }
+}
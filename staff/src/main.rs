@@ -2,7 +2,11 @@ use {
     atlas::{Atlas, Error as AtlasError, ImageData, Indent, Map, Parameters, TooLarge},
     clap::Parser,
     color::{Color, Error as ColorError, RepaintMode},
-    convert::{Element, Error as ParseError, Target, Value},
+    convert::{
+        Action, Axes, Axis, Bone, BvhError, Clip, Element, Error as ParseError, OpenError, Options,
+        Order, Output, Policy, Reference, RootMotion, Skeleton, SkeletonError, Target, Tolerance,
+        Value,
+    },
     serde_json::Error as JsonError,
     std::{
         collections::HashMap,
@@ -10,7 +14,7 @@ use {
         ffi::OsStr,
         fmt,
         fs::{self, File},
-        io::{self, BufWriter, Read, Write},
+        io::{self, BufRead, BufWriter, Read, Write},
         path::{Path, PathBuf},
         process::ExitCode,
     },
@@ -18,17 +22,137 @@ use {
 
 #[derive(Parser)]
 enum Cli {
-    /// Convert .dae objects to .json files
+    /// Convert .dae objects or .bvh motions to .json files
     Convert {
-        /// Target object to parse (mesh|skeleton|action)
+        /// Target object to parse (mesh|skeleton|action|all)
         target: Target,
 
-        /// File to parse (stdin by default)
+        /// File to parse, may be gzip compressed or a .zae archive (stdin by default)
         filepath: Option<PathBuf>,
 
         /// Specify output directory (current by default)
         #[arg(short, long)]
         outdir: Option<PathBuf>,
+
+        /// Write world-space bind matrices of skeleton bones
+        #[arg(long, default_value_t = false)]
+        bind: bool,
+
+        /// What to do with degenerate or orphaned bones (skip|reparent|fail)
+        #[arg(long, default_value = "skip")]
+        policy: Policy,
+
+        /// Convert only nodes whose names match the glob pattern
+        #[arg(short, long)]
+        select: Vec<String>,
+
+        /// Write action rotations as quaternions
+        #[arg(short, long, default_value_t = false)]
+        quaternion: bool,
+
+        /// Specify Euler rotation orders of bones file (XYZ by default)
+        #[arg(long)]
+        orders: Option<PathBuf>,
+
+        /// Bake actions to transform samples at the given frame rate
+        #[arg(long)]
+        bake: Option<f32>,
+
+        /// Remove action keys that can be reconstructed by interpolation
+        #[arg(long, default_value_t = false)]
+        reduce: bool,
+
+        /// Maximum rotation error in radians for key reduction
+        #[arg(long, default_value_t = 0.001)]
+        angle_tol: f32,
+
+        /// Maximum location and scale error for key reduction
+        #[arg(long, default_value_t = 0.001)]
+        pos_tol: f32,
+
+        /// Cut a frame range from actions as a separate action (name:start-end)
+        #[arg(long)]
+        clip: Vec<Clip>,
+
+        /// Frame rate of clip ranges and looped actions (24 or the .bvh frame rate by default)
+        #[arg(long)]
+        fps: Option<f32>,
+
+        /// Play actions backwards
+        #[arg(long, default_value_t = false)]
+        reverse: bool,
+
+        /// Make actions loop seamlessly by copying the first key after the end
+        #[arg(long = "loop", default_value_t = false)]
+        looping: bool,
+
+        /// Change the playback speed of actions
+        #[arg(long)]
+        speed: Option<f32>,
+
+        /// Extract root motion channels of the root bone to a separate action
+        #[arg(long, num_args = 0..=1, default_missing_value = "lx,lz,ry")]
+        root_motion: Option<Axes>,
+
+        /// Specify the root bone name (the top bone by default)
+        #[arg(long, requires = "root_motion")]
+        root_bone: Option<String>,
+
+        /// Also write actions and skeletons mirrored across the plane with the normal (x|y|z)
+        #[arg(long)]
+        mirror: Option<Axis>,
+
+        /// Make actions additive to the reference pose (rest or a name of action)
+        #[arg(long)]
+        additive: Option<Reference>,
+
+        /// Check action bones against the skeleton (.json or .dae file)
+        #[arg(long)]
+        skeleton: Option<PathBuf>,
+
+        /// Write bone indices of the skeleton instead of bone names
+        #[arg(long, default_value_t = false, requires = "skeleton")]
+        index: bool,
+    },
+    /// Retarget an action from .dae file to another skeleton
+    Retarget {
+        /// File with the action and its skeleton
+        filepath: PathBuf,
+
+        /// The target skeleton (.json or .dae file)
+        target: PathBuf,
+
+        /// Specify bone name mapping file from source to target names
+        #[arg(short, long)]
+        map: Option<PathBuf>,
+
+        /// Specify Euler rotation orders of bones file (XYZ by default)
+        #[arg(long)]
+        orders: Option<PathBuf>,
+
+        /// Specify output directory (current by default)
+        #[arg(short, long)]
+        outdir: Option<PathBuf>,
+    },
+    /// Export actions with their skeleton to .bvh files
+    Bvh {
+        /// The skeleton (.json, .dae or .bvh file)
+        skeleton: PathBuf,
+
+        /// File with actions (.json or .dae file)
+        action: PathBuf,
+
+        /// Frame rate of the exported motion
+        #[arg(long, default_value_t = 30.)]
+        fps: f32,
+
+        /// Specify Euler rotation orders of bones file (XYZ by default)
+        #[arg(long)]
+        orders: Option<PathBuf>,
+
+        /// Specify output directory (current by default)
+        #[arg(short, long)]
+        outdir: Option<PathBuf>,
     },
     /// Collect a palette from .png to .json file
     Collect {
@@ -119,16 +243,141 @@ fn run(cli: Cli) -> Result<(), Error> {
             target,
             filepath,
             outdir,
+            bind,
+            policy,
+            select,
+            quaternion,
+            orders,
+            bake,
+            reduce,
+            angle_tol,
+            pos_tol,
+            clip,
+            fps,
+            reverse,
+            looping,
+            speed,
+            root_motion,
+            root_bone,
+            mirror,
+            additive,
+            skeleton,
+            index,
         } => {
-            let src = read_string(filepath)?;
-            let elements = convert::parse(&src, target)?;
+            let bvh = filepath.as_deref().and_then(bvh_name);
+            let opts = Options {
+                bind,
+                policy,
+                select,
+                quaternion,
+                orders: read_orders(orders)?,
+                bake,
+                reduce: reduce.then_some(Tolerance {
+                    angular: angle_tol,
+                    positional: pos_tol,
+                }),
+                clips: clip,
+                frame_rate: fps,
+                reverse,
+                looping,
+                speed,
+                root_motion: root_motion.map(|axes| RootMotion {
+                    bone: root_bone,
+                    axes,
+                }),
+                mirror,
+                additive,
+                skeleton: match skeleton {
+                    Some(path) => Some(load_skeleton(&path)?),
+                    None => None,
+                },
+            };
+
+            let Output {
+                elements,
+                warnings,
+                reduced,
+            } = match &bvh {
+                Some(name) => convert::parse_bvh(&read_string(filepath)?, name, target, &opts)?,
+                None => convert::parse_reader(open(filepath)?, target, &opts)?,
+            };
+
+            for (name, bones) in reduced {
+                for (bone, removed) in bones {
+                    println!("removed {removed} keys of {bone} in {name}");
+                }
+            }
+
+            for warn in warnings {
+                eprintln!("warning: {warn}");
+            }
+
             if elements.is_empty() {
                 println!("no elements found");
                 return Ok(());
             }
 
             let outdir = make_outdir(outdir)?;
-            serialize_elements(&elements, &outdir)
+            let index = opts.skeleton.as_ref().filter(|_| index);
+            serialize_elements(&elements, &outdir, index, matches!(target, Target::All))
+        }
+        Cli::Retarget {
+            filepath,
+            target,
+            map,
+            orders,
+            outdir,
+        } => {
+            let source = read_skeleton(&filepath)?;
+            let actions = read_actions(&filepath)?;
+            let target = load_skeleton(&target)?;
+
+            let names = match map {
+                Some(path) => serde_json::from_str(&read_string(Some(path))?)?,
+                None => HashMap::default(),
+            };
+
+            let orders = read_orders(orders)?;
+            let mut warnings = vec![];
+            let elements: Vec<_> = actions
+                .into_iter()
+                .map(|(name, action)| Element {
+                    name,
+                    val: Value::Action(convert::retarget(
+                        &action,
+                        &source,
+                        &target,
+                        &names,
+                        &orders,
+                        &mut warnings,
+                    )),
+                })
+                .collect();
+
+            for warn in warnings {
+                eprintln!("warning: {warn}");
+            }
+
+            let outdir = make_outdir(outdir)?;
+            serialize_elements(&elements, &outdir, None, false)
+        }
+        Cli::Bvh {
+            skeleton,
+            action,
+            fps,
+            orders,
+            outdir,
+        } => {
+            let sk = load_skeleton(&skeleton)?;
+            let actions = load_actions(&action)?;
+            let orders = read_orders(orders)?;
+            let outdir = make_outdir(outdir)?;
+            for (name, action) in actions {
+                let src = convert::write_bvh(&sk, &action, fps, &orders)?;
+                write_bvh(&src, &name, &outdir)?;
+            }
+
+            Ok(())
         }
         Cli::Collect {
             filepath,
@@ -230,6 +479,95 @@ fn read_string(path: Option<PathBuf>) -> Result<String, Error> {
     }
 }
 
+/// Opens the collada document of the file, or stdin, to read as it streams.
+fn open(path: Option<PathBuf>) -> Result<Box<dyn BufRead>, Error> {
+    match path {
+        Some(path) => convert::open(&path).map_err(|err| Error::Open(path, err)),
+        None => convert::unpack(io::stdin().lock()).map_err(Error::Unpack),
+    }
+}
+
+fn read_skeleton(path: &Path) -> Result<Skeleton, Error> {
+    let src = open(Some(path.to_owned()))?;
+    let Output { elements, .. } =
+        convert::parse_reader(src, Target::Skeleton, &Options::default())?;
+    elements
+        .into_iter()
+        .find_map(|el| match el.val {
+            Value::Skeleton(sk) => Some(sk),
+            _ => None,
+        })
+        .ok_or_else(|| Error::NoSkeleton(path.to_owned()))
+}
+
+fn load_skeleton(path: &Path) -> Result<Skeleton, Error> {
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let src = read_string(Some(path.to_owned()))?;
+        let bones: Vec<Bone> = serde_json::from_str(&src)?;
+        Ok(Skeleton::from_bones(bones)?)
+    } else if let Some(name) = bvh_name(path) {
+        let src = read_string(Some(path.to_owned()))?;
+        let opts = Options::default();
+        let Output { elements, .. } = convert::parse_bvh(&src, &name, Target::Skeleton, &opts)?;
+        elements
+            .into_iter()
+            .find_map(|el| match el.val {
+                Value::Skeleton(sk) => Some(sk),
+                _ => None,
+            })
+            .ok_or_else(|| Error::NoSkeleton(path.to_owned()))
+    } else {
+        read_skeleton(path)
+    }
+}
+
+/// Returns the file name if it's a `.bvh` file.
+fn bvh_name(path: &Path) -> Option<String> {
+    if path.extension()? != "bvh" {
+        return None;
+    }
+
+    let name = path.file_stem()?.to_str()?;
+    Some(name.to_owned())
+}
+
+fn read_orders(path: Option<PathBuf>) -> Result<HashMap<String, Order>, Error> {
+    match path {
+        Some(path) => Ok(serde_json::from_str(&read_string(Some(path))?)?),
+        None => Ok(HashMap::default()),
+    }
+}
+
+/// Reads bone actions from `.dae` file.
+fn read_actions(path: &Path) -> Result<Vec<(String, Action)>, Error> {
+    let src = open(Some(path.to_owned()))?;
+    let Output { elements, .. } = convert::parse_reader(src, Target::Action, &Options::default())?;
+    let actions: Vec<_> = elements
+        .into_iter()
+        .filter_map(|Element { name, val }| match val {
+            Value::Action(act) => Some((name, act)),
+            _ => None,
+        })
+        .collect();
+
+    if actions.is_empty() {
+        return Err(Error::NoAction(path.to_owned()));
+    }
+
+    Ok(actions)
+}
+
+/// Loads a converted action from `.json` file or actions from `.dae` file.
+fn load_actions(path: &Path) -> Result<Vec<(String, Action)>, Error> {
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let src = read_string(Some(path.to_owned()))?;
+        let name = path.file_stem().and_then(OsStr::to_str).unwrap_or("action");
+        Ok(vec![(name.to_owned(), serde_json::from_str(&src)?)])
+    } else {
+        read_actions(path)
+    }
+}
+
 fn read_data(path: Option<PathBuf>) -> Result<Vec<u8>, Error> {
     let stdin_read = || {
         let mut buf = Vec::new();
@@ -276,9 +614,31 @@ fn make_outdir(outdir: Option<PathBuf>) -> Result<PathBuf, Error> {
     Ok(outdir)
 }
 
-fn serialize_elements(elements: &[Element], outdir: &Path) -> Result<(), Error> {
+/// Writes elements to `.json` files.
+///
+/// If `split` is set, elements are written to subdirectories of their kinds.
+fn serialize_elements(
+    elements: &[Element],
+    outdir: &Path,
+    index: Option<&Skeleton>,
+    split: bool,
+) -> Result<(), Error> {
     for Element { name, val } in elements {
-        let mut path = outdir.join(name);
+        let mut path = if split {
+            let dir = outdir.join(val.kind());
+            make_outdir(Some(dir))?
+        } else {
+            outdir.to_owned()
+        };
+
+        // Object actions may be named like bone ones, so they get a suffix
+        match val {
+            Value::ObjectAction(_) | Value::ObjectBaked(_) if !split => {
+                path.push(format!("{name}_object"));
+            }
+            _ => path.push(name),
+        }
+
         path.set_extension("json");
         println!("write element to file {path:?}");
         let file = {
@@ -286,10 +646,15 @@ fn serialize_elements(elements: &[Element], outdir: &Path) -> Result<(), Error>
             BufWriter::new(file)
         };
 
-        match val {
-            Value::Mesh(mesh) => serde_json::to_writer(file, &mesh),
-            Value::Skeleton(sk) => serde_json::to_writer(file, sk.bones()),
-            Value::Action(act) => serde_json::to_writer(file, act.keyframes()),
+        match (val, index) {
+            (Value::Mesh(mesh), _) => serde_json::to_writer(file, &mesh),
+            (Value::Skeleton(sk), _) => serde_json::to_writer(file, sk.bones()),
+            (Value::Action(act), Some(sk)) => serde_json::to_writer(file, &act.indexed(sk)),
+            (Value::Action(act) | Value::ObjectAction(act), _) => serde_json::to_writer(file, act),
+            (Value::Baked(baked), Some(sk)) => serde_json::to_writer(file, &baked.indexed(sk)),
+            (Value::Baked(baked) | Value::ObjectBaked(baked), _) => {
+                serde_json::to_writer(file, baked)
+            }
         }
         .expect("serialize element");
     }
@@ -323,6 +688,13 @@ fn serialize_map(map: &Map, name: &str, outdir: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+fn write_bvh(src: &str, name: &str, outdir: &Path) -> Result<(), Error> {
+    let mut path = outdir.join(name);
+    path.set_extension("bvh");
+    println!("write motion to file {path:?}");
+    fs::write(&path, src).map_err(|_| Error::WriteToFile(path))
+}
+
 fn write_png(data: &[u8], name: &str, outdir: &Path) -> Result<(), Error> {
     let mut path = outdir.join(name);
     path.set_extension("png");
@@ -338,13 +710,19 @@ fn write_png(data: &[u8], name: &str, outdir: &Path) -> Result<(), Error> {
 enum Error {
     ReadFile(PathBuf),
     ReadStdin,
+    Open(PathBuf, OpenError),
+    Unpack(OpenError),
     OutDir,
     CreateFile(PathBuf),
     WriteToFile(PathBuf),
     PalettePathNotSet,
+    NoSkeleton(PathBuf),
+    NoAction(PathBuf),
     Atlas(AtlasError),
     Indent(TooLarge),
     Parse(ParseError),
+    Bvh(BvhError),
+    Skeleton(SkeletonError),
     Color(ColorError),
     Json(JsonError),
 }
@@ -367,6 +745,18 @@ impl From<ParseError> for Error {
     }
 }
 
+impl From<BvhError> for Error {
+    fn from(v: BvhError) -> Self {
+        Self::Bvh(v)
+    }
+}
+
+impl From<SkeletonError> for Error {
+    fn from(v: SkeletonError) -> Self {
+        Self::Skeleton(v)
+    }
+}
+
 impl From<ColorError> for Error {
     fn from(v: ColorError) -> Self {
         Self::Color(v)
@@ -384,13 +774,19 @@ impl fmt::Display for Error {
         match self {
             Self::ReadFile(path) => write!(f, "failed to read file {path:?}"),
             Self::ReadStdin => write!(f, "failed to read stdin"),
+            Self::Open(path, err) => write!(f, "failed to open file {path:?}: {err}"),
+            Self::Unpack(err) => write!(f, "failed to unpack stdin: {err}"),
             Self::OutDir => write!(f, "failed to get output directory"),
             Self::CreateFile(path) => write!(f, "failed to create the file {path:?}"),
             Self::WriteToFile(path) => write!(f, "failed to write file {path:?}"),
             Self::PalettePathNotSet => write!(f, "the palette path is not set"),
+            Self::NoSkeleton(path) => write!(f, "no skeleton found in {path:?}"),
+            Self::NoAction(path) => write!(f, "no action found in {path:?}"),
             Self::Atlas(err) => write!(f, "{err}"),
             Self::Indent(err) => write!(f, "{err}"),
             Self::Parse(err) => write!(f, "{err}"),
+            Self::Bvh(err) => write!(f, "{err}"),
+            Self::Skeleton(err) => write!(f, "{err}"),
             Self::Color(err) => write!(f, "{err}"),
             Self::Json(err) => write!(f, "{err}"),
         }